@@ -0,0 +1,630 @@
+use crate::erosion::{erode, ErosionFields};
+use crate::height::HeightSampler;
+use crate::lakes::{generate_lakes, LakeMode};
+use crate::random::{region_seed, Rng};
+use crate::TerrainConfig;
+
+// Seamless erosion. Instead of eroding each chunk on its own, the world is
+// covered by overlapping erosion regions whose droplets are seeded from the
+// region's own coordinates: one tile per chunk, one seam per chunk edge
+// (centred on the edge) and one patch per chunk corner. Every region extends
+// `erosion_apron` cells past the area where it is used, so its droplets flow
+// freely across chunk borders. The final height is the noise height plus a
+// weighted sum of the regions' erosion deltas. Within one cell of a chunk
+// edge only the seam and corner regions contribute, and both chunks sharing
+// that edge evaluate exactly the same regions in the same order, which makes
+// the heights (and the normals derived from them) bit-identical along seams.
+// For the same reason the lake levels along the edges come from flooding the
+// seam and corner regions on their own rather than the chunk's tile.
+
+// Kind 0 is used by chunks eroded on their own.
+#[derive(Clone, Copy)]
+enum RegionKind {
+    Tile = 1,
+    VerticalSeam = 2,
+    HorizontalSeam = 3,
+    Corner = 4,
+}
+
+struct ErodedRegion {
+    origin_x: i32,
+    origin_z: i32,
+    width: u32,
+    deltas: Vec<f32>,
+    fields: ErosionFields,
+}
+
+impl ErodedRegion {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        kind: RegionKind,
+        region_x: i32,
+        region_z: i32,
+        origin_x: i32,
+        origin_z: i32,
+        width: u32,
+        depth: u32,
+        seed: u32,
+        sampler: &HeightSampler,
+        config: &TerrainConfig,
+    ) -> ErodedRegion {
+        let mut rng = Rng::new(region_seed(seed, kind as u32, region_x, region_z));
+
+        let mut noise_heights = Vec::with_capacity(((width + 1) * (depth + 1)) as usize);
+        for z in 0..=depth {
+            for x in 0..=width {
+                noise_heights
+                    .push(sampler.calculate_noise_height(origin_x + x as i32, origin_z + z as i32));
+            }
+        }
+
+        let mut deltas = noise_heights.clone();
+        let fields = erode(&mut deltas, width, depth, &mut rng, config);
+        for (delta, noise_height) in deltas.iter_mut().zip(noise_heights.iter()) {
+            *delta -= noise_height;
+        }
+
+        ErodedRegion {
+            origin_x,
+            origin_z,
+            width,
+            deltas,
+            fields,
+        }
+    }
+
+    fn index(&self, x: i32, z: i32) -> usize {
+        let local_x = (x - self.origin_x) as usize;
+        let local_z = (z - self.origin_z) as usize;
+        local_z * (self.width as usize + 1) + local_x
+    }
+}
+
+// The water level of every lake entry of the given area, 0 elsewhere, with the
+// heights of `terms` regions blended by their weights at each entry.
+fn region_lake_levels<'a>(
+    origin: (i32, i32),
+    width: i32,
+    depth: i32,
+    terms: &dyn Fn(i32, i32) -> [(f32, &'a ErodedRegion); 2],
+    sampler: &HeightSampler,
+    config: &TerrainConfig,
+) -> Vec<f32> {
+    let mut heights = Vec::with_capacity(((width + 1) * (depth + 1)) as usize);
+    for z in origin.1..=origin.1 + depth {
+        for x in origin.0..=origin.0 + width {
+            let mut height = sampler.calculate_noise_height(x, z);
+            for (weight, region) in terms(x, z) {
+                if weight > 0.0 {
+                    height += weight * region.deltas[region.index(x, z)];
+                }
+            }
+            heights.push(height);
+        }
+    }
+    generate_lakes(&mut heights, width as u32, depth as u32, 0, 0, 0, config).1
+}
+
+// Weight of the seam regions at `distance` cells from a chunk edge: 1 on the
+// edge and the cell next to it, easing out to 0 at `apron` cells.
+fn seam_weight(distance: i32, apron: i32) -> f32 {
+    if distance <= 1 {
+        1.0
+    } else if distance >= apron {
+        0.0
+    } else {
+        let t = (apron - distance) as f32 / (apron - 1) as f32;
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// The entries of a map holding a chunk and `ring` entries around it that
+/// belong to the chunk itself.
+pub fn crop_ring(values: &[f32], width: u32, depth: u32, ring: u32) -> Vec<f32> {
+    let row = (width + 2 * ring + 1) as usize;
+    let ring = ring as usize;
+    (ring..=ring + depth as usize)
+        .flat_map(|z| &values[z * row + ring..=z * row + ring + width as usize])
+        .copied()
+        .collect()
+}
+
+/// How much of a change made to a seamlessly eroded chunk at `(x, z)` to
+/// keep: none within a cell of the chunk edges, which the neighbouring chunks
+/// share, easing in to all of it `apron` cells in like the tile's erosion.
+pub fn edge_weight(x: u32, z: u32, width: u32, depth: u32, apron: u32) -> f32 {
+    let distance = x.min(width - x).min(z).min(depth - z);
+    1.0 - seam_weight(distance as i32, apron as i32)
+}
+
+/// A chunk's height map with `ring` extra cells on every side, so normals
+/// along the chunk edges can be computed from eroded neighbours. `erosion`
+/// holds how far erosion moved each height away from the noise height, and
+/// `fields` the other erosion results blended like the heights.
+pub struct PaddedHeightMap {
+    width: u32,
+    depth: u32,
+    ring: u32,
+    offset_x: i32,
+    offset_z: i32,
+    heights: Vec<f32>,
+    erosion: Vec<f32>,
+    fields: ErosionFields,
+    edge_lake_levels: Vec<(usize, f32)>,
+}
+
+impl PaddedHeightMap {
+    fn index(&self, x: i32, z: i32) -> usize {
+        let ring = self.ring as i32;
+        let local_x = (x - self.offset_x + ring) as usize;
+        let local_z = (z - self.offset_z + ring) as usize;
+        local_z * (self.width + 2 * self.ring + 1) as usize + local_x
+    }
+
+    pub fn get(&self, x: i32, z: i32) -> f32 {
+        self.heights[self.index(x, z)]
+    }
+
+    fn crop(&self, values: &[f32], ring: u32) -> Vec<f32> {
+        let ring = ring as i32;
+        let (width, depth) = (self.width as i32 + ring, self.depth as i32 + ring);
+        let mut cropped = Vec::with_capacity(((width + ring + 1) * (depth + ring + 1)) as usize);
+        for z in -ring..=depth {
+            for x in -ring..=width {
+                cropped.push(values[self.index(self.offset_x + x, self.offset_z + z)]);
+            }
+        }
+        cropped
+    }
+
+    pub fn to_height_map(&self) -> Vec<f32> {
+        self.crop(&self.heights, 0)
+    }
+
+    /// The heights of the chunk and `ring` cells around it, which must not be
+    /// more than the ring the map was generated with.
+    pub fn to_padded_height_map(&self, ring: u32) -> Vec<f32> {
+        self.crop(&self.heights, ring)
+    }
+
+    pub fn to_erosion_map(&self) -> Vec<f32> {
+        self.crop(&self.erosion, 0)
+    }
+
+    /// Replaces the lake levels along the edges of the chunk's
+    /// `lake_level_map` by those of the seam and corner regions, which the
+    /// neighbouring chunks share.
+    pub fn set_edge_lake_levels(&self, lake_level_map: &mut [f32]) {
+        for &(index, level) in self.edge_lake_levels.iter() {
+            lake_level_map[index] = level;
+        }
+    }
+
+    pub fn to_erosion_fields(&self) -> ErosionFields {
+        let mut fields = ErosionFields::default();
+        for (map, padded_map) in fields.maps_mut().iter_mut().zip(self.fields.maps()) {
+            if !padded_map.is_empty() {
+                *map = self.crop(padded_map, 0);
+            }
+        }
+        fields
+    }
+}
+
+/// Generates the seamlessly eroded heights of a chunk and `ring` cells around
+/// it, at least one and at most `erosion_apron`.
+pub fn generate_apron_height_map(
+    chunk_x: i32,
+    chunk_z: i32,
+    ring: u32,
+    seed: u32,
+    sampler: &HeightSampler,
+    config: &TerrainConfig,
+) -> PaddedHeightMap {
+    let width = config.chunk_width as i32;
+    let depth = config.chunk_depth as i32;
+    let apron = config.erosion_apron as i32;
+    let ring = ring.clamp(1, config.erosion_apron);
+    let offset_x = chunk_x * width;
+    let offset_z = chunk_z * depth;
+
+    let region = |kind, region_x, region_z, origin_x, origin_z, region_width, region_depth| {
+        ErodedRegion::new(
+            kind,
+            region_x,
+            region_z,
+            origin_x,
+            origin_z,
+            region_width as u32,
+            region_depth as u32,
+            seed,
+            sampler,
+            config,
+        )
+    };
+
+    let tile = region(
+        RegionKind::Tile,
+        chunk_x,
+        chunk_z,
+        offset_x - apron,
+        offset_z - apron,
+        width + 2 * apron,
+        depth + 2 * apron,
+    );
+    let vertical_seams: Vec<ErodedRegion> = (0..2)
+        .map(|i| {
+            region(
+                RegionKind::VerticalSeam,
+                chunk_x + i,
+                chunk_z,
+                (chunk_x + i) * width - 2 * apron,
+                offset_z - apron,
+                4 * apron,
+                depth + 2 * apron,
+            )
+        })
+        .collect();
+    let horizontal_seams: Vec<ErodedRegion> = (0..2)
+        .map(|j| {
+            region(
+                RegionKind::HorizontalSeam,
+                chunk_x,
+                chunk_z + j,
+                offset_x - apron,
+                (chunk_z + j) * depth - 2 * apron,
+                width + 2 * apron,
+                4 * apron,
+            )
+        })
+        .collect();
+    let corners: Vec<ErodedRegion> = (0..4)
+        .map(|k| {
+            let (i, j) = (k % 2, k / 2);
+            region(
+                RegionKind::Corner,
+                chunk_x + i,
+                chunk_z + j,
+                (chunk_x + i) * width - 2 * apron,
+                (chunk_z + j) * depth - 2 * apron,
+                4 * apron,
+                4 * apron,
+            )
+        })
+        .collect();
+
+    let padded = ring as i32;
+    let mut heights =
+        Vec::with_capacity(((width + 2 * padded + 1) * (depth + 2 * padded + 1)) as usize);
+    let mut erosion = Vec::with_capacity(heights.capacity());
+    let mut fields = ErosionFields::default();
+    let produced_fields: Vec<bool> = tile
+        .fields
+        .maps()
+        .iter()
+        .map(|map| !map.is_empty())
+        .collect();
+    for local_z in -padded..=depth + padded {
+        let (j, distance_z) = if local_z <= depth / 2 {
+            (0, local_z.abs())
+        } else {
+            (1, (depth - local_z).abs())
+        };
+        let weight_z = seam_weight(distance_z, apron);
+        for local_x in -padded..=width + padded {
+            let (i, distance_x) = if local_x <= width / 2 {
+                (0, local_x.abs())
+            } else {
+                (1, (width - local_x).abs())
+            };
+            let weight_x = seam_weight(distance_x, apron);
+            let x = offset_x + local_x;
+            let z = offset_z + local_z;
+
+            // The order of these terms must not depend on which chunk is
+            // being generated.
+            let terms = [
+                (weight_x * weight_z, &corners[(j * 2 + i) as usize]),
+                (weight_x * (1.0 - weight_z), &vertical_seams[i as usize]),
+                ((1.0 - weight_x) * weight_z, &horizontal_seams[j as usize]),
+                ((1.0 - weight_x) * (1.0 - weight_z), &tile),
+            ];
+            let blend = |values: &dyn Fn(&ErodedRegion) -> &[f32]| {
+                let mut value = 0.0;
+                for &(weight, region) in terms.iter() {
+                    if weight > 0.0 {
+                        value += weight * values(region)[region.index(x, z)];
+                    }
+                }
+                value
+            };
+
+            let delta = blend(&|region| &region.deltas);
+            heights.push(sampler.calculate_noise_height(x, z) + delta);
+            erosion.push(delta);
+            for (k, map) in fields.maps_mut().iter_mut().enumerate() {
+                if produced_fields[k] {
+                    map.push(blend(&|region| &region.fields.maps()[k]));
+                }
+            }
+        }
+    }
+
+    let mut edge_lake_levels = Vec::new();
+    if config.lake_mode == LakeMode::Lakes {
+        let row = width as usize + 1;
+        let distance = |local: i32, size: i32| {
+            if local <= size / 2 {
+                (0, local.abs())
+            } else {
+                (1, (size - local).abs())
+            }
+        };
+        for i in 0..2 {
+            let levels = region_lake_levels(
+                ((chunk_x + i) * width - 2 * apron, offset_z - apron),
+                4 * apron,
+                depth + 2 * apron,
+                &|_, z| {
+                    let (j, distance_z) = distance(z - offset_z, depth);
+                    let weight_z = seam_weight(distance_z, apron);
+                    [
+                        (weight_z, &corners[(j * 2 + i) as usize]),
+                        (1.0 - weight_z, &vertical_seams[i as usize]),
+                    ]
+                },
+                sampler,
+                config,
+            );
+            for z in 0..=depth {
+                let level = levels[((z + apron) * (4 * apron + 1) + 2 * apron) as usize];
+                edge_lake_levels.push((z as usize * row + (i * width) as usize, level));
+            }
+        }
+        for j in 0..2 {
+            let levels = region_lake_levels(
+                (offset_x - apron, (chunk_z + j) * depth - 2 * apron),
+                width + 2 * apron,
+                4 * apron,
+                &|x, _| {
+                    let (i, distance_x) = distance(x - offset_x, width);
+                    let weight_x = seam_weight(distance_x, apron);
+                    [
+                        (weight_x, &corners[(j * 2 + i) as usize]),
+                        (1.0 - weight_x, &horizontal_seams[j as usize]),
+                    ]
+                },
+                sampler,
+                config,
+            );
+            for x in 0..=width {
+                let level = levels[(2 * apron * (width + 2 * apron + 1) + x + apron) as usize];
+                edge_lake_levels.push(((j * depth) as usize * row + x as usize, level));
+            }
+        }
+        for k in 0..4 {
+            let (i, j) = (k % 2, k / 2);
+            let levels = region_lake_levels(
+                (
+                    (chunk_x + i) * width - 2 * apron,
+                    (chunk_z + j) * depth - 2 * apron,
+                ),
+                4 * apron,
+                4 * apron,
+                &|_, _| [(1.0, &corners[k as usize]), (0.0, &tile)],
+                sampler,
+                config,
+            );
+            let level = levels[(2 * apron * (4 * apron + 1) + 2 * apron) as usize];
+            edge_lake_levels.push(((j * depth) as usize * row + (i * width) as usize, level));
+        }
+    }
+
+    PaddedHeightMap {
+        width: width as u32,
+        depth: depth as u32,
+        ring,
+        offset_x,
+        offset_z,
+        heights,
+        erosion,
+        fields,
+        edge_lake_levels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::height::HeightSampler;
+    use crate::{
+        Biome, BiomeTable, Chunk, ColorRamp, HeightProfile, LakeMode, TerrainConfig,
+        TerrainGenerator,
+    };
+
+    const SEED: u32 = 7;
+
+    fn config() -> TerrainConfig {
+        TerrainConfig {
+            chunk_width: 32,
+            chunk_depth: 32,
+            erosion_apron: 8,
+            erosion_edge_damp_max_distance: 6.0,
+            erosion_stop_height_start: 0.02,
+            erosion_stop_height_end: 0.01,
+            ..TerrainConfig::default()
+        }
+    }
+
+    fn bits(values: impl Iterator<Item = f32>) -> Vec<u32> {
+        values.map(f32::to_bits).collect()
+    }
+
+    type ChunkValues = fn(&Chunk) -> &[f32];
+
+    // The values a chunk and the one to its right hold along their shared
+    // edge, `stride` floats per height map entry.
+    fn shared_edge(
+        left: &Chunk,
+        right: &Chunk,
+        values: ChunkValues,
+        stride: usize,
+    ) -> (Vec<u32>, Vec<u32>) {
+        let row = left.width as usize + 1;
+        let column = |chunk: &Chunk, x: usize| {
+            bits((0..=chunk.depth as usize).flat_map(|z| {
+                let index = (z * row + x) * stride;
+                values(chunk)[index..index + stride].iter().copied()
+            }))
+        };
+        (column(left, row - 1), column(right, 0))
+    }
+
+    #[test]
+    fn neighbouring_chunks_have_identical_edges() {
+        let config = config();
+        let generator = TerrainGenerator::new(SEED, config).unwrap();
+        let sampler = HeightSampler::new(SEED, &config, None);
+        let row = config.chunk_width as usize + 1;
+        let column_count = config.chunk_depth as usize + 1;
+
+        let chunk = generator.generate_chunk(3, -2);
+        let right = generator.generate_chunk(4, -2);
+        let below = generator.generate_chunk(3, -1);
+
+        let right_edge = bits((0..column_count).map(|z| chunk.height_map[z * row + row - 1]));
+        let left_edge = bits((0..column_count).map(|z| right.height_map[z * row]));
+        assert_eq!(right_edge, left_edge);
+
+        let bottom_edge = bits(chunk.height_map[(column_count - 1) * row..].iter().copied());
+        let top_edge = bits(below.height_map[..row].iter().copied());
+        assert_eq!(bottom_edge, top_edge);
+
+        let bottom_normals = bits(
+            chunk.normals[(column_count - 1) * row * 3..]
+                .iter()
+                .copied(),
+        );
+        let top_normals = bits(below.normals[..row * 3].iter().copied());
+        assert_eq!(bottom_normals, top_normals);
+
+        let eroded = (0..row).any(|x| {
+            let noise_height = sampler.calculate_noise_height(3 * 32 + x as i32, -32);
+            below.height_map[x] != noise_height
+        });
+        assert!(eroded, "the shared edge should have been eroded");
+    }
+
+    #[test]
+    fn lakes_leave_the_edges_of_neighbouring_chunks_identical() {
+        let config = TerrainConfig {
+            lake_mode: LakeMode::Lakes,
+            lake_min_depth: 0.1,
+            ..config()
+        };
+        let mut hills = Biome::new("hills", ColorRamp::preset("temperate").unwrap());
+        hills.height_profile = Some(HeightProfile {
+            octaves: Some(3),
+            persistence: Some(0.3),
+            ..HeightProfile::default()
+        });
+        let mut generator = TerrainGenerator::new(SEED, config).unwrap();
+        generator.set_biome_table(Some(BiomeTable::new(vec![hills]).unwrap()));
+
+        // A lake lies across the edge between these two chunks.
+        let left = generator.generate_chunk(30, 25);
+        let right = generator.generate_chunk(31, 25);
+        let (left_levels, right_levels) =
+            shared_edge(&left, &right, |chunk| &chunk.lake_level_map, 1);
+        assert_eq!(left_levels, right_levels);
+        assert!(left_levels.iter().any(|&level| level != 0));
+        let edges: [(ChunkValues, usize); 3] = [
+            (|chunk| &chunk.height_map, 1),
+            (|chunk| &chunk.normals, 3),
+            (|chunk| &chunk.colors, 3),
+        ];
+        for (values, stride) in edges {
+            let (left_edge, right_edge) = shared_edge(&left, &right, values, stride);
+            assert_eq!(left_edge, right_edge);
+        }
+    }
+
+    #[test]
+    fn carved_rivers_leave_the_edges_of_neighbouring_chunks_identical() {
+        let config = TerrainConfig {
+            chunk_width: 64,
+            chunk_depth: 64,
+            erosion_apron: 16,
+            river_catchment_threshold: 20.0,
+            river_carve: true,
+            ..config()
+        };
+        let generator = TerrainGenerator::new(SEED, config).unwrap();
+        let left = generator.generate_chunk(0, 0);
+        let right = generator.generate_chunk(1, 0);
+
+        let crosses_edge = |chunk: &Chunk| {
+            chunk.rivers.iter().any(|river| {
+                let (first, last) = (river.vertices[0], *river.vertices.last().unwrap());
+                (first.x < 64.0) != (last.x < 64.0)
+            })
+        };
+        assert!(crosses_edge(&left) && crosses_edge(&right));
+        let edges: [(ChunkValues, usize); 3] = [
+            (|chunk| &chunk.height_map, 1),
+            (|chunk| &chunk.normals, 3),
+            (|chunk| &chunk.colors, 3),
+        ];
+        for (values, stride) in edges {
+            let (left_edge, right_edge) = shared_edge(&left, &right, values, stride);
+            assert_eq!(left_edge, right_edge);
+        }
+    }
+
+    #[test]
+    fn neighbouring_chunks_have_the_same_climate_and_biomes_along_their_edges() {
+        let config = TerrainConfig {
+            lake_mode: LakeMode::Lakes,
+            lake_min_depth: 0.1,
+            river_catchment_threshold: 20.0,
+            river_carve: true,
+            ..config()
+        };
+        let mut generator = TerrainGenerator::new(SEED, config).unwrap();
+        generator.set_biome_table(Some(BiomeTable::default()));
+
+        let left = generator.generate_chunk(30, 25);
+        let right = generator.generate_chunk(31, 25);
+        assert!(!left.lakes.is_empty() && !left.rivers.is_empty());
+        let edges: [(ChunkValues, usize); 4] = [
+            (|chunk| &chunk.temperature_map, 1),
+            (|chunk| &chunk.moisture_map, 1),
+            (|chunk| &chunk.height_map, 1),
+            (|chunk| &chunk.colors, 3),
+        ];
+        for (values, stride) in edges {
+            let (left_edge, right_edge) = shared_edge(&left, &right, values, stride);
+            assert_eq!(left_edge, right_edge);
+        }
+        let row = config.chunk_width as usize + 1;
+        for z in 0..row {
+            assert_eq!(left.biome_map[z * row + row - 1], right.biome_map[z * row]);
+        }
+    }
+
+    #[test]
+    fn rejects_aprons_wider_than_half_a_chunk() {
+        for erosion_apron in [17, u32::MAX / 2 + 1, u32::MAX] {
+            let config = TerrainConfig {
+                erosion_apron,
+                ..config()
+            };
+            assert!(config.validate().is_err());
+        }
+        assert!(TerrainConfig {
+            erosion_apron: 16,
+            ..config()
+        }
+        .validate()
+        .is_ok());
+    }
+}
@@ -0,0 +1,463 @@
+use rust_webpack_template::{
+    encode_glb, encode_obj, encode_stl, BiomeTable, Chunk, ColorRamp, FlowRouting, HeightGraph,
+    HeightMap, LakeMode, NoiseFunction, SplatLayers, SplatMap, TerrainConfig, TerrainGenerator,
+};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "Usage: terrain [options]
+
+Generates terrain chunks and writes them to disk in one or more formats:
+  raw    every chunk's buffers as little-endian binary files, together with
+         a small JSON description of the chunk
+  png16  the height map of the whole region as a 16 bit grayscale PNG
+  r16    the height map of the whole region as little-endian 16 bit RAW
+  glb    the meshes of the whole region as binary glTF, one node per chunk
+  obj    the meshes of the whole region as Wavefront OBJ with vertex colors
+  stl    the surface of the whole region as binary STL, z up
+  splat  the splat weights of the whole region as 8 bit RGBA PNGs holding
+         four layers each (splatmap_0.png, splatmap_1.png)
+Heights in png16 and r16 are scaled so that 65535 is the terrain's maximum
+height, which is also written to heightmap.json. With --stl-base, the STL is
+closed into a watertight solid for 3D printing. With --splat, raw exports also
+include every chunk's splat weights, and with --pipe its water and sediment
+maps, and with --droplet-maps its flow, deposition and erosion depth maps.
+With --rivers, raw exports include every chunk's flow accumulation and its
+rivers as JSON polylines (rivers.json), and with --lakes lakes its lake level
+map and lakes with their outlines (lakes.json). With --biomes, they include
+every chunk's temperature and moisture and its biome indices (biomes.u8).
+
+Options:
+  --seed <seed>                  World seed (default 0)
+  --chunk <x> <z>                Generate a single chunk (default 0 0)
+  --region <x0> <z0> <x1> <z1>   Generate every chunk from (x0, z0) to (x1, z1) inclusive
+  --size <width>[x<depth>]       Chunk size in cells, powers of two (default 1024)
+  --noise <function>             Noise summed by the octaves: supersimplex, perlin,
+                                 opensimplex, worley, ridged or billow (default supersimplex)
+  --warp <levels>                Domain warp the noise with 1 or 2 levels (default 0)
+  --warp-amplitude <units>       How far the domain warp moves features (default 80)
+  --continents <units>           Shape continents and oceans this many world units
+                                 per noise unit, e.g. 16384 (default off)
+  --land-ratio <fraction>        Fraction of the world on continents (default 0.4)
+  --height-graph <file.json>     Generate heights from a JSON graph of noise layers
+                                 instead of the single noise above
+  --apron <cells>                Enable seamless erosion with the given apron (default 0)
+  --thermal <iterations>         Thermal erosion iterations after hydraulic erosion (default 0)
+  --pipe <iterations>            Pipe model erosion timesteps after the droplets (default 0)
+  --droplet-maps                 Record the droplets' water flow, deposition and erosion depth
+  --rivers <cells>               Trace rivers draining at least this many cells (default off)
+  --flow-routing <d8|dinfinity>  How water is routed between cells for rivers (default d8)
+  --carve-rivers                 Cut the river beds into the terrain
+  --lakes <fill|lakes>           Fill the terrain's depressions or turn them into lakes
+  --lake-min-depth <depth>       Fill depressions shallower than this instead (default 0.5)
+  --sea-level <fraction>         Sea level as a fraction of the maximum height (default 0.37)
+  --stl-base <depth>             Close the STL with walls and a base this far below
+                                 the lowest point
+  --colors <preset|file.json>    Color ramp: temperate, desert, arctic, volcanic, taiga,
+                                 savanna, jungle or a JSON file (default temperate)
+  --biomes <default|file.json>   Color each biome with its own ramp: arctic, taiga,
+                                 temperate, desert, savanna and jungle, or a JSON file
+  --wind <degrees>               Direction the wind carries moisture in (default 0, +x)
+  --blend-radius <units>         Distance biome height profiles blend over (default 32)
+  --splat <default|file.json>    Splat layers: sand, grass, rock and snow, or a JSON
+                                 file (default none, or default with splat export)
+  --out <directory>              Output directory (default terrain-out)
+  --export <format>[,<format>]   Output formats (default raw)
+  --help                         Print this message";
+
+#[derive(PartialEq)]
+enum Export {
+    Raw,
+    Png16,
+    R16,
+    Glb,
+    Obj,
+    Stl,
+    Splat,
+}
+
+struct Options {
+    seed: u32,
+    chunks: (i32, i32, i32, i32),
+    config: TerrainConfig,
+    out: PathBuf,
+    exports: Vec<Export>,
+    stl_base: Option<f32>,
+    color_ramp: ColorRamp,
+    splat_layers: Option<SplatLayers>,
+    height_graph: Option<HeightGraph>,
+    biome_table: Option<BiomeTable>,
+}
+
+fn parse<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        seed: 0,
+        chunks: (0, 0, 0, 0),
+        config: TerrainConfig::default(),
+        out: PathBuf::from("terrain-out"),
+        exports: vec![Export::Raw],
+        stl_base: None,
+        color_ramp: ColorRamp::default(),
+        splat_layers: None,
+        height_graph: None,
+        biome_table: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => options.seed = parse(&arg, args.next())?,
+            "--chunk" => {
+                let x = parse(&arg, args.next())?;
+                let z = parse(&arg, args.next())?;
+                options.chunks = (x, z, x, z);
+            }
+            "--region" => {
+                let x0: i32 = parse(&arg, args.next())?;
+                let z0: i32 = parse(&arg, args.next())?;
+                let x1: i32 = parse(&arg, args.next())?;
+                let z1: i32 = parse(&arg, args.next())?;
+                options.chunks = (x0.min(x1), z0.min(z1), x0.max(x1), z0.max(z1));
+            }
+            "--size" => {
+                let size: String = parse(&arg, args.next())?;
+                let mut parts = size.splitn(2, 'x');
+                let width = parse(&arg, parts.next().map(String::from))?;
+                let depth = match parts.next() {
+                    Some(depth) => parse(&arg, Some(depth.to_string()))?,
+                    None => width,
+                };
+                options.config.chunk_width = width;
+                options.config.chunk_depth = depth;
+            }
+            "--noise" => {
+                let noise: String = parse(&arg, args.next())?;
+                options.config.noise_function = match noise.as_str() {
+                    "supersimplex" => NoiseFunction::SuperSimplex,
+                    "perlin" => NoiseFunction::Perlin,
+                    "opensimplex" => NoiseFunction::OpenSimplex,
+                    "worley" => NoiseFunction::Worley,
+                    "ridged" => NoiseFunction::Ridged,
+                    "billow" => NoiseFunction::Billow,
+                    _ => return Err(format!("unknown noise function: {}", noise)),
+                };
+            }
+            "--warp" => options.config.warp_levels = parse(&arg, args.next())?,
+            "--warp-amplitude" => options.config.warp_amplitude = parse(&arg, args.next())?,
+            "--continents" => options.config.continent_fineness = parse(&arg, args.next())?,
+            "--land-ratio" => options.config.continent_land_ratio = parse(&arg, args.next())?,
+            "--apron" => options.config.erosion_apron = parse(&arg, args.next())?,
+            "--rivers" => options.config.river_catchment_threshold = parse(&arg, args.next())?,
+            "--flow-routing" => {
+                let routing: String = parse(&arg, args.next())?;
+                options.config.river_flow_routing = match routing.as_str() {
+                    "d8" => FlowRouting::D8,
+                    "dinfinity" => FlowRouting::DInfinity,
+                    _ => return Err(format!("unknown flow routing: {}", routing)),
+                };
+            }
+            "--lakes" => {
+                let mode: String = parse(&arg, args.next())?;
+                options.config.lake_mode = match mode.as_str() {
+                    "fill" => LakeMode::Fill,
+                    "lakes" => LakeMode::Lakes,
+                    _ => return Err(format!("unknown lake mode: {}", mode)),
+                };
+            }
+            "--lake-min-depth" => options.config.lake_min_depth = parse(&arg, args.next())?,
+            "--sea-level" => options.config.sea_level = parse(&arg, args.next())?,
+            "--carve-rivers" => options.config.river_carve = true,
+            "--droplet-maps" => options.config.erosion_droplet_maps = true,
+            "--pipe" => options.config.pipe_erosion_iterations = parse(&arg, args.next())?,
+            "--thermal" => options.config.thermal_erosion_iterations = parse(&arg, args.next())?,
+            "--stl-base" => {
+                let base_depth: f32 = parse(&arg, args.next())?;
+                if !base_depth.is_finite() || base_depth <= 0.0 {
+                    return Err(format!(
+                        "--stl-base must be positive and finite, not {}",
+                        base_depth
+                    ));
+                }
+                options.stl_base = Some(base_depth);
+            }
+            "--colors" => {
+                let colors: String = parse(&arg, args.next())?;
+                options.color_ramp = if colors.ends_with(".json") {
+                    let json = fs::read_to_string(&colors)
+                        .map_err(|error| format!("{}: {}", colors, error))?;
+                    ColorRamp::from_json(&json).map_err(|error| format!("{}: {}", colors, error))?
+                } else {
+                    ColorRamp::preset(&colors)?
+                };
+            }
+            "--splat" => {
+                let splat: String = parse(&arg, args.next())?;
+                options.splat_layers = Some(if splat == "default" {
+                    SplatLayers::default()
+                } else {
+                    let json = fs::read_to_string(&splat)
+                        .map_err(|error| format!("{}: {}", splat, error))?;
+                    SplatLayers::from_json(&json)
+                        .map_err(|error| format!("{}: {}", splat, error))?
+                });
+            }
+            "--height-graph" => {
+                let path: String = parse(&arg, args.next())?;
+                let json =
+                    fs::read_to_string(&path).map_err(|error| format!("{}: {}", path, error))?;
+                options.height_graph = Some(
+                    HeightGraph::from_json(&json)
+                        .map_err(|error| format!("{}: {}", path, error))?,
+                );
+            }
+            "--biomes" => {
+                let biomes: String = parse(&arg, args.next())?;
+                options.biome_table = Some(if biomes == "default" {
+                    BiomeTable::default()
+                } else {
+                    let json = fs::read_to_string(&biomes)
+                        .map_err(|error| format!("{}: {}", biomes, error))?;
+                    BiomeTable::from_json(&json)
+                        .map_err(|error| format!("{}: {}", biomes, error))?
+                });
+            }
+            "--wind" => options.config.climate_wind_direction = parse(&arg, args.next())?,
+            "--blend-radius" => options.config.biome_blend_radius = parse(&arg, args.next())?,
+            "--out" => options.out = PathBuf::from(parse::<String>(&arg, args.next())?),
+            "--export" => {
+                let formats: String = parse(&arg, args.next())?;
+                options.exports = formats
+                    .split(',')
+                    .map(|format| match format {
+                        "raw" => Ok(Export::Raw),
+                        "png16" => Ok(Export::Png16),
+                        "r16" => Ok(Export::R16),
+                        "glb" => Ok(Export::Glb),
+                        "obj" => Ok(Export::Obj),
+                        "stl" => Ok(Export::Stl),
+                        "splat" => Ok(Export::Splat),
+                        _ => Err(format!("unknown export format: {}", format)),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "--help" | "-h" => return Ok(None),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(Some(options))
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    fs::write(path, bytes).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+fn write_f32s(path: &Path, values: &[f32]) -> Result<(), String> {
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    write(path, &bytes)
+}
+
+fn write_u32s(path: &Path, values: &[u32]) -> Result<(), String> {
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    write(path, &bytes)
+}
+
+fn write_chunk(
+    out: &Path,
+    seed: u32,
+    chunk_x: i32,
+    chunk_z: i32,
+    chunk: &Chunk,
+    config: &TerrainConfig,
+) -> Result<(), String> {
+    let name = format!("chunk_{}_{}", chunk_x, chunk_z);
+    let file = |extension: &str| out.join(format!("{}.{}", name, extension));
+    write_f32s(&file("heights.f32"), &chunk.height_map)?;
+    write_f32s(&file("vertices.f32"), &chunk.vertices)?;
+    write_f32s(&file("normals.f32"), &chunk.normals)?;
+    write_f32s(&file("colors.f32"), &chunk.colors)?;
+    write_u32s(&file("indices.u32"), &chunk.indices)?;
+    if chunk.splat_layer_count > 0 {
+        write_f32s(&file("splat.f32"), &chunk.splat_weights)?;
+    }
+    if !chunk.water_map.is_empty() {
+        write_f32s(&file("water.f32"), &chunk.water_map)?;
+        write_f32s(&file("sediment.f32"), &chunk.sediment_map)?;
+    }
+    if !chunk.flow_map.is_empty() {
+        write_f32s(&file("flow.f32"), &chunk.flow_map)?;
+        write_f32s(&file("deposition.f32"), &chunk.deposition_map)?;
+        write_f32s(&file("erosion_depth.f32"), &chunk.erosion_depth_map)?;
+    }
+    if !chunk.flow_accumulation_map.is_empty() {
+        write_f32s(&file("flow_accumulation.f32"), &chunk.flow_accumulation_map)?;
+        let rivers = serde_json::to_string_pretty(&chunk.rivers).unwrap();
+        write(&file("rivers.json"), rivers.as_bytes())?;
+    }
+    if !chunk.lake_level_map.is_empty() {
+        write_f32s(&file("lake_level.f32"), &chunk.lake_level_map)?;
+        let lakes = serde_json::to_string_pretty(&chunk.lakes).unwrap();
+        write(&file("lakes.json"), lakes.as_bytes())?;
+    }
+    if !chunk.biome_map.is_empty() {
+        write_f32s(&file("temperature.f32"), &chunk.temperature_map)?;
+        write_f32s(&file("moisture.f32"), &chunk.moisture_map)?;
+        write(&file("biomes.u8"), &chunk.biome_map)?;
+    }
+    let description = format!(
+        "{{\n  \"seed\": {},\n  \"chunk_x\": {},\n  \"chunk_z\": {},\n  \"width\": {},\n  \"depth\": {},\n  \"max_height\": {},\n  \"splat_layer_count\": {}\n}}\n",
+        seed, chunk_x, chunk_z, chunk.width, chunk.depth, config.max_height, chunk.splat_layer_count
+    );
+    write(&file("json"), description.as_bytes())
+}
+
+fn write_height_map(out: &Path, options: &Options, height_map: &HeightMap) -> Result<(), String> {
+    let max_height = options.config.max_height;
+    if options.exports.contains(&Export::Png16) {
+        write(
+            &out.join("heightmap.png"),
+            &height_map.encode_png(max_height)?,
+        )?;
+    }
+    if options.exports.contains(&Export::R16) {
+        write(
+            &out.join("heightmap.r16"),
+            &height_map.encode_r16(max_height),
+        )?;
+    }
+    let (x0, z0, x1, z1) = options.chunks;
+    let description = format!(
+        "{{\n  \"seed\": {},\n  \"chunks\": [{}, {}, {}, {}],\n  \"width\": {},\n  \"depth\": {},\n  \"max_height\": {}\n}}\n",
+        options.seed, x0, z0, x1, z1, height_map.width, height_map.depth, max_height
+    );
+    write(&out.join("heightmap.json"), description.as_bytes())
+}
+
+fn run() -> Result<(), String> {
+    let options = match parse_options(env::args().skip(1))? {
+        Some(options) => options,
+        None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+    let (x0, z0, x1, z1) = options.chunks;
+    let mut generator = TerrainGenerator::new(options.seed, options.config)?;
+    generator.set_color_ramp(options.color_ramp.clone());
+    generator.set_height_graph(options.height_graph.clone());
+    generator.set_biome_table(options.biome_table.clone());
+    let splat_layers = match &options.splat_layers {
+        Some(layers) => Some(layers.clone()),
+        None if options.exports.contains(&Export::Splat) => Some(SplatLayers::default()),
+        None => None,
+    };
+    let mut splat_map = if options.exports.contains(&Export::Splat) {
+        splat_layers.as_ref().map(|layers| {
+            SplatMap::for_region(
+                (x1 - x0 + 1) as u32,
+                (z1 - z0 + 1) as u32,
+                options.config.chunk_width,
+                options.config.chunk_depth,
+                layers.len() as u32,
+            )
+        })
+    } else {
+        None
+    };
+    generator.set_splat_layers(splat_layers);
+    fs::create_dir_all(&options.out)
+        .map_err(|error| format!("{}: {}", options.out.display(), error))?;
+
+    let mut height_map = if options
+        .exports
+        .iter()
+        .any(|export| matches!(export, Export::Png16 | Export::R16 | Export::Stl))
+    {
+        Some(HeightMap::for_region(
+            (x1 - x0 + 1) as u32,
+            (z1 - z0 + 1) as u32,
+            options.config.chunk_width,
+            options.config.chunk_depth,
+        ))
+    } else {
+        None
+    };
+    let mut chunks = Vec::new();
+    for chunk_z in z0..=z1 {
+        for chunk_x in x0..=x1 {
+            println!("Generating chunk ({}, {})", chunk_x, chunk_z);
+            let chunk = generator.generate_chunk(chunk_x, chunk_z);
+            if options.exports.contains(&Export::Raw) {
+                write_chunk(
+                    &options.out,
+                    options.seed,
+                    chunk_x,
+                    chunk_z,
+                    &chunk,
+                    &options.config,
+                )?;
+            }
+            if let Some(height_map) = &mut height_map {
+                height_map.insert_chunk((chunk_x - x0) as u32, (chunk_z - z0) as u32, &chunk);
+            }
+            if let Some(splat_map) = &mut splat_map {
+                splat_map.insert_chunk((chunk_x - x0) as u32, (chunk_z - z0) as u32, &chunk);
+            }
+            if options
+                .exports
+                .iter()
+                .any(|export| matches!(export, Export::Glb | Export::Obj))
+            {
+                chunks.push(chunk);
+            }
+        }
+    }
+    if let Some(height_map) = &height_map {
+        if options.exports.contains(&Export::Stl) {
+            write(
+                &options.out.join("terrain.stl"),
+                &encode_stl(height_map, options.stl_base)?,
+            )?;
+        }
+        write_height_map(&options.out, &options, height_map)?;
+    }
+    if let Some(splat_map) = &splat_map {
+        for page in 0..splat_map.page_count() {
+            write(
+                &options.out.join(format!("splatmap_{}.png", page)),
+                &splat_map.encode_png(page)?,
+            )?;
+        }
+    }
+    let chunks: Vec<&Chunk> = chunks.iter().collect();
+    if options.exports.contains(&Export::Glb) {
+        write(&options.out.join("terrain.glb"), &encode_glb(&chunks)?)?;
+    }
+    if options.exports.contains(&Export::Obj) {
+        write(
+            &options.out.join("terrain.obj"),
+            encode_obj(&chunks).as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("terrain: {}", message);
+        eprintln!("Run `terrain --help` for usage.");
+        process::exit(1);
+    }
+}
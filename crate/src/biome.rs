@@ -0,0 +1,572 @@
+use crate::climate::{climate_grid, CLIMATE_GRID_SPACING};
+use crate::color::{range_weight, ColorRamp};
+use crate::TerrainConfig;
+use serde::{Deserialize, Deserializer, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+pub const MAX_BIOMES: usize = 256;
+
+const BIOME_CLIMATE_FADE: f32 = 0.05;
+
+// Height profiles are picked by the climate of the uneroded terrain, on the
+// coarse grid of nodes moisture is computed on.
+
+/// The noise parameters of a biome's terrain, replacing those of the
+/// `TerrainConfig` (or the height graph) wherever the biome lies. Parameters
+/// left out are taken from the config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeightProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub octaves: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persistence: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lacunarity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_slope: Option<f32>,
+}
+
+impl HeightProfile {
+    fn validate(&self) -> Result<(), String> {
+        let persistence = self.persistence.unwrap_or(1.0);
+        let checks = [
+            (
+                (1..=16).contains(&self.octaves.unwrap_or(1)),
+                "octaves must be between 1 and 16",
+            ),
+            (
+                persistence > 0.0 && persistence <= 1.0,
+                "persistence must be in (0, 1]",
+            ),
+            (
+                self.lacunarity.unwrap_or(1.0) >= 1.0,
+                "lacunarity must be at least 1",
+            ),
+            (
+                self.noise_slope.unwrap_or(1.0) > 0.0,
+                "noise_slope must be positive",
+            ),
+        ];
+        match checks.iter().find(|(valid, _)| !valid) {
+            Some((_, message)) => Err(message.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One biome of a `BiomeTable`, covering the climates within its
+/// temperature and moisture ranges, both in [0, 1]. Missing bounds are
+/// unlimited. `color_ramp` colors the terrain of the biome, and can be given
+/// in JSON as the name of a preset or as a whole color ramp. With a
+/// `height_profile`, the biome also shapes its terrain with its own noise.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Biome {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_moisture: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_moisture: Option<f32>,
+    #[serde(deserialize_with = "preset_or_color_ramp")]
+    pub color_ramp: ColorRamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height_profile: Option<HeightProfile>,
+}
+
+fn preset_or_color_ramp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ColorRamp, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PresetOrColorRamp {
+        Preset(String),
+        ColorRamp(ColorRamp),
+    }
+
+    match PresetOrColorRamp::deserialize(deserializer)? {
+        PresetOrColorRamp::Preset(name) => {
+            ColorRamp::preset(&name).map_err(serde::de::Error::custom)
+        }
+        PresetOrColorRamp::ColorRamp(ramp) => Ok(ramp),
+    }
+}
+
+impl Biome {
+    pub fn new(name: &str, color_ramp: ColorRamp) -> Biome {
+        Biome {
+            name: name.to_string(),
+            min_temperature: None,
+            max_temperature: None,
+            min_moisture: None,
+            max_moisture: None,
+            color_ramp,
+            height_profile: None,
+        }
+    }
+
+    fn weight(&self, temperature: f32, moisture: f32) -> f32 {
+        range_weight(
+            temperature,
+            self.min_temperature,
+            self.max_temperature,
+            BIOME_CLIMATE_FADE,
+        ) * range_weight(
+            moisture,
+            self.min_moisture,
+            self.max_moisture,
+            BIOME_CLIMATE_FADE,
+        )
+    }
+}
+
+/// Up to `MAX_BIOMES` biomes, laid out like a Whittaker diagram over
+/// temperature and moisture. Biomes blend into each other where their
+/// ranges meet, and a vertex belongs to the biome with the largest weight;
+/// where no biome matches, the first biome is used.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BiomeTable {
+    biomes: Vec<Biome>,
+}
+
+impl Default for BiomeTable {
+    /// Arctic, taiga, temperate, desert, savanna and jungle.
+    fn default() -> BiomeTable {
+        let biome = |name: &str, preset: &str| Biome::new(name, ColorRamp::preset(preset).unwrap());
+        let arctic = Biome {
+            max_temperature: Some(0.2),
+            ..biome("arctic", "arctic")
+        };
+        let taiga = Biome {
+            min_temperature: Some(0.2),
+            max_temperature: Some(0.45),
+            ..biome("taiga", "taiga")
+        };
+        let temperate = Biome {
+            min_temperature: Some(0.45),
+            max_temperature: Some(0.75),
+            min_moisture: Some(0.3),
+            ..biome("temperate", "temperate")
+        };
+        let desert = Biome {
+            min_temperature: Some(0.45),
+            max_moisture: Some(0.3),
+            ..biome("desert", "desert")
+        };
+        let savanna = Biome {
+            min_temperature: Some(0.75),
+            min_moisture: Some(0.3),
+            max_moisture: Some(0.6),
+            ..biome("savanna", "savanna")
+        };
+        let jungle = Biome {
+            min_temperature: Some(0.75),
+            min_moisture: Some(0.6),
+            ..biome("jungle", "jungle")
+        };
+        BiomeTable {
+            biomes: vec![arctic, taiga, temperate, desert, savanna, jungle],
+        }
+    }
+}
+
+impl BiomeTable {
+    pub fn new(biomes: Vec<Biome>) -> Result<BiomeTable, String> {
+        let table = BiomeTable { biomes };
+        table.validate()?;
+        Ok(table)
+    }
+
+    pub fn from_json(json: &str) -> Result<BiomeTable, String> {
+        let table: BiomeTable = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        table.validate()?;
+        Ok(table)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn biomes(&self) -> &[Biome] {
+        &self.biomes
+    }
+
+    pub fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.biomes.is_empty() || self.biomes.len() > MAX_BIOMES {
+            return Err(format!(
+                "expected 1 to {} biomes, got {}",
+                MAX_BIOMES,
+                self.biomes.len()
+            ));
+        }
+        for biome in self.biomes.iter() {
+            let ranges = [
+                ("temperature", biome.min_temperature, biome.max_temperature),
+                ("moisture", biome.min_moisture, biome.max_moisture),
+            ];
+            for &(name, min, max) in ranges.iter() {
+                if min.into_iter().chain(max).any(|bound| !bound.is_finite()) {
+                    return Err(format!(
+                        "biome {} has a non-finite {} bound",
+                        biome.name, name
+                    ));
+                }
+                if let (Some(min), Some(max)) = (min, max) {
+                    if min > max {
+                        return Err(format!(
+                            "biome {} has min_{} {} above max_{} {}",
+                            biome.name, name, min, name, max
+                        ));
+                    }
+                }
+            }
+            biome
+                .color_ramp
+                .validate()
+                .map_err(|error| format!("biome {}: {}", biome.name, error))?;
+            if let Some(profile) = &biome.height_profile {
+                profile
+                    .validate()
+                    .map_err(|error| format!("biome {}: {}", biome.name, error))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the normalized weight of every biome to `weights` and returns
+    /// the index of the heaviest.
+    pub fn weights_at(&self, temperature: f32, moisture: f32, weights: &mut [f32]) -> usize {
+        let mut total = 0.0;
+        let mut heaviest = 0;
+        let mut max_weight = 0.0;
+        for (i, (weight, biome)) in weights.iter_mut().zip(self.biomes.iter()).enumerate() {
+            *weight = biome.weight(temperature, moisture);
+            total += *weight;
+            if *weight > max_weight {
+                heaviest = i;
+                max_weight = *weight;
+            }
+        }
+        if total > 0.0 {
+            for weight in weights.iter_mut() {
+                *weight /= total;
+            }
+        } else {
+            weights[0] = 1.0;
+        }
+        heaviest
+    }
+}
+
+/// The biome weights of a chunk and the area around it that its heights are
+/// sampled in, on a coarse grid. The weights follow the climate of the
+/// uneroded terrain and are blurred over `biome_blend_radius` cells, so
+/// height profiles blend smoothly where biomes meet.
+pub struct BiomeWeightGrid {
+    origin_x: i32,
+    origin_z: i32,
+    nodes_x: usize,
+    nodes_z: usize,
+    biome_count: usize,
+    weights: Vec<f32>,
+}
+
+impl BiomeWeightGrid {
+    /// `height` gives the uneroded terrain height at a point.
+    pub fn new(
+        table: &BiomeTable,
+        height: &dyn Fn(i32, i32) -> f32,
+        chunk_x: i32,
+        chunk_z: i32,
+        config: &TerrainConfig,
+    ) -> BiomeWeightGrid {
+        let spacing = CLIMATE_GRID_SPACING;
+        // Seamless erosion samples heights up to twice the apron past the chunk.
+        let margin =
+            config.biome_blend_radius.ceil() as i32 + 2 * config.erosion_apron as i32 + 2 * spacing;
+        let x0 = chunk_x * config.chunk_width as i32 - margin;
+        let z0 = chunk_z * config.chunk_depth as i32 - margin;
+        let x1 = (chunk_x + 1) * config.chunk_width as i32 + margin;
+        let z1 = (chunk_z + 1) * config.chunk_depth as i32 + margin;
+        let (origin_x, origin_z) = (
+            x0.div_euclid(spacing) * spacing,
+            z0.div_euclid(spacing) * spacing,
+        );
+        let nodes_x = ((x1 - origin_x) / spacing + 2) as usize;
+        let nodes_z = ((z1 - origin_z) / spacing + 2) as usize;
+        let (temperatures, moistures) = climate_grid(
+            height,
+            (origin_x, origin_z),
+            nodes_x,
+            nodes_z,
+            spacing,
+            config,
+        );
+
+        let biome_count = table.len();
+        let mut weights = vec![0.0; nodes_x * nodes_z * biome_count];
+        for (node, node_weights) in weights.chunks_mut(biome_count).enumerate() {
+            table.weights_at(temperatures[node], moistures[node], node_weights);
+        }
+        let radius = (config.biome_blend_radius / spacing as f32).round() as usize;
+        if radius > 0 {
+            blur(&mut weights, nodes_x, nodes_z, biome_count, radius);
+        }
+
+        BiomeWeightGrid {
+            origin_x,
+            origin_z,
+            nodes_x,
+            nodes_z,
+            biome_count,
+            weights,
+        }
+    }
+
+    /// The weight of every biome at a point, interpolated between the nodes
+    /// around it.
+    pub fn weights_at(&self, x: f32, z: f32) -> impl Iterator<Item = f32> + '_ {
+        let spacing = CLIMATE_GRID_SPACING as f32;
+        let grid_x = ((x - self.origin_x as f32) / spacing).clamp(0.0, (self.nodes_x - 1) as f32);
+        let grid_z = ((z - self.origin_z as f32) / spacing).clamp(0.0, (self.nodes_z - 1) as f32);
+        let (node_x, node_z) = (
+            (grid_x as usize).min(self.nodes_x - 2),
+            (grid_z as usize).min(self.nodes_z - 2),
+        );
+        let (fraction_x, fraction_z) = (grid_x - node_x as f32, grid_z - node_z as f32);
+        let node = |x: usize, z: usize| {
+            let start = (z * self.nodes_x + x) * self.biome_count;
+            &self.weights[start..start + self.biome_count]
+        };
+        let corners = [
+            (
+                node(node_x, node_z),
+                (1.0 - fraction_x) * (1.0 - fraction_z),
+            ),
+            (node(node_x + 1, node_z), fraction_x * (1.0 - fraction_z)),
+            (node(node_x, node_z + 1), (1.0 - fraction_x) * fraction_z),
+            (node(node_x + 1, node_z + 1), fraction_x * fraction_z),
+        ];
+        (0..self.biome_count).map(move |biome| {
+            corners
+                .iter()
+                .map(|(corner, corner_weight)| corner[biome] * corner_weight)
+                .sum()
+        })
+    }
+}
+
+// Box blur of `channels` interleaved values per node along both axes,
+// averaging over the nodes within `radius` that lie on the grid.
+fn blur(values: &mut [f32], width: usize, depth: usize, channels: usize, radius: usize) {
+    let mut blurred = vec![0.0; values.len()];
+    for (stride, length, lines, line_stride) in [(1, width, depth, width), (width, depth, width, 1)]
+    {
+        for line in 0..lines {
+            for i in 0..length {
+                let (start, end) = (i.saturating_sub(radius), (i + radius).min(length - 1));
+                for channel in 0..channels {
+                    let sum: f32 = (start..=end)
+                        .map(|j| values[(line * line_stride + j * stride) * channels + channel])
+                        .sum();
+                    blurred[(line * line_stride + i * stride) * channels + channel] =
+                        sum / (end - start + 1) as f32;
+                }
+            }
+        }
+        values.copy_from_slice(&blurred);
+    }
+}
+
+/// The biome of every vertex and the vertex colors, blended from the color
+/// ramps of the biomes each vertex is weighted towards.
+pub fn build_biome_colors(
+    height_map: &[f32],
+    normals: &[f32],
+    curvatures: &[f32],
+    temperature_map: &[f32],
+    moisture_map: &[f32],
+    table: &BiomeTable,
+    config: &TerrainConfig,
+) -> (Vec<u8>, Vec<f32>) {
+    let mut biome_map = Vec::with_capacity(height_map.len());
+    let mut colors = Vec::with_capacity(height_map.len() * 3);
+    let mut weights = vec![0.0; table.len()];
+    for (i, &height) in height_map.iter().enumerate() {
+        let slope = normals[i * 3 + 1].clamp(-1.0, 1.0).acos().to_degrees();
+        let biome = table.weights_at(temperature_map[i], moisture_map[i], &mut weights);
+        biome_map.push(biome as u8);
+        let mut color = [0.0; 3];
+        for (weight, biome) in weights.iter().zip(table.biomes.iter()) {
+            if *weight > 0.0 {
+                let biome_color = biome.color_ramp.surface_color(
+                    height / config.max_height,
+                    slope,
+                    curvatures[i],
+                );
+                for channel in 0..3 {
+                    color[channel] += biome_color[channel] * weight;
+                }
+            }
+        }
+        colors.extend_from_slice(&color);
+    }
+    (biome_map, colors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_biome_colors, Biome, BiomeTable, BiomeWeightGrid, HeightProfile};
+    use crate::color::ColorRamp;
+    use crate::height::HeightSampler;
+    use crate::TerrainConfig;
+
+    #[test]
+    fn classifies_climates_like_a_whittaker_diagram() {
+        let table = BiomeTable::default();
+        let mut weights = vec![0.0; table.len()];
+        let mut biome = |temperature, moisture| {
+            table.biomes()[table.weights_at(temperature, moisture, &mut weights)]
+                .name
+                .as_str()
+        };
+        assert_eq!(biome(0.1, 0.5), "arctic");
+        assert_eq!(biome(0.3, 0.1), "taiga");
+        assert_eq!(biome(0.6, 0.8), "temperate");
+        assert_eq!(biome(0.9, 0.1), "desert");
+        assert_eq!(biome(0.9, 0.45), "savanna");
+        assert_eq!(biome(0.9, 0.9), "jungle");
+
+        let biome = table.weights_at(0.6, 0.3, &mut weights);
+        assert_eq!((weights[2], weights[3]), (0.5, 0.5));
+        assert!(biome == 2 || biome == 3);
+    }
+
+    #[test]
+    fn reads_presets_and_whole_ramps_from_json() {
+        let table = BiomeTable::from_json(
+            r#"{"biomes": [
+                {"name": "cold", "max_temperature": 0.5, "color_ramp": "arctic"},
+                {"name": "warm", "min_temperature": 0.5,
+                 "color_ramp": {"stops": [{"height": 1.0, "color": [255, 0, 0]}]}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            table.biomes()[0].color_ramp,
+            ColorRamp::preset("arctic").unwrap()
+        );
+        assert_eq!(BiomeTable::from_json(&table.to_json()).unwrap(), table);
+        assert!(BiomeTable::from_json(r#"{"biomes": []}"#).is_err());
+        assert!(
+            BiomeTable::from_json(r#"{"biomes": [{"name": "lunar", "color_ramp": "lunar"}]}"#)
+                .is_err()
+        );
+
+        let config = TerrainConfig::default();
+        let (biome_map, colors) = build_biome_colors(
+            &[config.max_height; 2],
+            &[0.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+            &[0.0; 2],
+            &[0.9, 0.1],
+            &[0.5; 2],
+            &table,
+            &config,
+        );
+        assert_eq!(biome_map, vec![1, 0]);
+        assert_eq!(&colors[..3], &[1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn blends_height_profiles_across_biome_borders() {
+        // Without a lapse rate the biomes only follow z: alps to the north
+        // of z = 1024 and plains to the south.
+        let config = TerrainConfig {
+            chunk_width: 64,
+            chunk_depth: 64,
+            climate_pole_distance: 2048.0,
+            climate_lapse_rate: 0.0,
+            ..TerrainConfig::default()
+        };
+        let alps = HeightProfile {
+            octaves: Some(8),
+            persistence: Some(0.6),
+            noise_slope: Some(0.6),
+            ..HeightProfile::default()
+        };
+        let plains = HeightProfile {
+            octaves: Some(2),
+            persistence: Some(0.2),
+            noise_slope: Some(2.0),
+            ..HeightProfile::default()
+        };
+        let mut cold = Biome::new("alps", ColorRamp::preset("arctic").unwrap());
+        cold.max_temperature = Some(0.5);
+        cold.height_profile = Some(alps);
+        let mut warm = Biome::new("plains", ColorRamp::preset("temperate").unwrap());
+        warm.min_temperature = Some(0.5);
+        warm.height_profile = Some(plains);
+        let table = BiomeTable::new(vec![cold, warm]).unwrap();
+
+        let sampler = |chunk_z: i32| {
+            let base = HeightSampler::new(7, &config, None);
+            let grid = BiomeWeightGrid::new(
+                &table,
+                &|x, z| base.calculate_base_height(x, z),
+                0,
+                chunk_z,
+                &config,
+            );
+            base.with_biomes(7, &table, grid)
+        };
+        let profile_only = |profile: HeightProfile| {
+            let config = TerrainConfig {
+                octaves: profile.octaves.unwrap(),
+                persistence: profile.persistence.unwrap(),
+                noise_slope: profile.noise_slope.unwrap(),
+                ..config
+            };
+            HeightSampler::new(7, &config, None)
+        };
+        let (alps_only, plains_only) = (profile_only(alps), profile_only(plains));
+
+        let south = sampler(0);
+        let north = sampler(32);
+        for x in 0..64 {
+            assert_eq!(
+                south.calculate_noise_height(x, 10),
+                plains_only.calculate_noise_height(x, 10)
+            );
+            assert_eq!(
+                north.calculate_noise_height(x, 2058),
+                alps_only.calculate_noise_height(x, 2058)
+            );
+        }
+
+        let (above, below) = (sampler(15), sampler(16));
+        let mut blended = 0;
+        for x in 0..64 {
+            let height = below.calculate_noise_height(x, 1024);
+            let (a, b) = (
+                alps_only.calculate_noise_height(x, 1024),
+                plains_only.calculate_noise_height(x, 1024),
+            );
+            assert!(height >= a.min(b) - 1e-3 && height <= a.max(b) + 1e-3);
+            if (height - a).abs() > 1e-3 && (height - b).abs() > 1e-3 {
+                blended += 1;
+            }
+            assert!((height - above.calculate_noise_height(x, 1024)).abs() < 1e-3);
+        }
+        assert!(blended > 32);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{max, min, TerrainConfig};
+
+// Temperature falls linearly from the equator at z = 0 to the poles and with
+// altitude above the sea. Moisture comes from the sea two ways: it spreads
+// evenly around it, fading over `climate_moisture_range` cells, and the
+// prevailing wind carries it downwind, fading over `climate_wind_range`
+// cells and losing more wherever the air has to climb. Moisture is computed
+// from the uneroded heights on a world-aligned grid of nodes, each node
+// looking only this many of the ranges around it, so every point gets the
+// same moisture whichever chunk it is computed for. Lakes and rivers depend
+// on the chunk they are found in, so they do not add any.
+const CLIMATE_REACH_RANGES: f32 = 4.0;
+
+/// Spacing in cells of the nodes moisture is computed on.
+pub const CLIMATE_GRID_SPACING: i32 = 16;
+
+// Temperature in [0, 1] at world row `z` and the given height.
+fn temperature_at(z: f32, height: f32, config: &TerrainConfig) -> f32 {
+    let latitude = min(z.abs() / config.climate_pole_distance, 1.0);
+    let altitude = max(height / config.max_height - config.sea_level, 0.0)
+        / max(1.0 - config.sea_level, f32::EPSILON);
+    (1.0 - latitude - config.climate_lapse_rate * altitude).clamp(0.0, 1.0)
+}
+
+/// Temperature of every height map entry, in [0, 1] from the coldest to
+/// the hottest.
+pub fn temperature_map(
+    height_map: &[f32],
+    width: u32,
+    depth: u32,
+    chunk_z: i32,
+    config: &TerrainConfig,
+) -> Vec<f32> {
+    let row = width as usize + 1;
+    height_map
+        .iter()
+        .enumerate()
+        .map(|(index, &height)| {
+            let z = chunk_z as f32 * depth as f32 + (index / row) as f32;
+            temperature_at(z, height, config)
+        })
+        .collect()
+}
+
+/// Moisture of every height map entry, in [0, 1] from the driest to the
+/// wettest, interpolated between the grid nodes around it. `height` gives the
+/// uneroded terrain height at a point.
+pub fn moisture_map(
+    width: u32,
+    depth: u32,
+    chunk_x: i32,
+    chunk_z: i32,
+    height: &dyn Fn(i32, i32) -> f32,
+    config: &TerrainConfig,
+) -> Vec<f32> {
+    let spacing = CLIMATE_GRID_SPACING;
+    let (x0, z0) = (chunk_x * width as i32, chunk_z * depth as i32);
+    let (node_x0, node_z0) = (x0.div_euclid(spacing), z0.div_euclid(spacing));
+    let nodes_x = ((x0 + width as i32).div_euclid(spacing) - node_x0 + 2) as usize;
+    let nodes_z = ((z0 + depth as i32).div_euclid(spacing) - node_z0 + 2) as usize;
+    let moistures = node_moistures(
+        height,
+        (node_x0 * spacing, node_z0 * spacing),
+        nodes_x,
+        nodes_z,
+        spacing,
+        config,
+    );
+
+    let mut moisture = Vec::with_capacity(((width + 1) * (depth + 1)) as usize);
+    for z in z0..=z0 + depth as i32 {
+        let node_z = (z.div_euclid(spacing) - node_z0) as usize;
+        let fraction_z = z.rem_euclid(spacing) as f32 / spacing as f32;
+        for x in x0..=x0 + width as i32 {
+            let node_x = (x.div_euclid(spacing) - node_x0) as usize;
+            let fraction_x = x.rem_euclid(spacing) as f32 / spacing as f32;
+            let node = |x: usize, z: usize| moistures[z * nodes_x + x];
+            moisture.push(
+                node(node_x, node_z) * (1.0 - fraction_x) * (1.0 - fraction_z)
+                    + node(node_x + 1, node_z) * fraction_x * (1.0 - fraction_z)
+                    + node(node_x, node_z + 1) * (1.0 - fraction_x) * fraction_z
+                    + node(node_x + 1, node_z + 1) * fraction_x * fraction_z,
+            );
+        }
+    }
+    moisture
+}
+
+/// Temperature and moisture of the terrain given by `height`, on a grid of
+/// `nodes_x` by `nodes_z` points `spacing` world units apart starting at
+/// `(origin_x, origin_z)`.
+pub fn climate_grid(
+    height: &dyn Fn(i32, i32) -> f32,
+    origin: (i32, i32),
+    nodes_x: usize,
+    nodes_z: usize,
+    spacing: i32,
+    config: &TerrainConfig,
+) -> (Vec<f32>, Vec<f32>) {
+    let mut temperatures = Vec::with_capacity(nodes_x * nodes_z);
+    for node_z in 0..nodes_z {
+        for node_x in 0..nodes_x {
+            let x = origin.0 + node_x as i32 * spacing;
+            let z = origin.1 + node_z as i32 * spacing;
+            temperatures.push(temperature_at(z as f32, height(x, z), config));
+        }
+    }
+    let moistures = node_moistures(height, origin, nodes_x, nodes_z, spacing, config);
+    (temperatures, moistures)
+}
+
+// Moisture of every node of the grid, each from the nodes within reach of it
+// alone. The heights are sampled on the grid widened by that reach.
+fn node_moistures(
+    height: &dyn Fn(i32, i32) -> f32,
+    origin: (i32, i32),
+    nodes_x: usize,
+    nodes_z: usize,
+    spacing: i32,
+    config: &TerrainConfig,
+) -> Vec<f32> {
+    let sea_height = config.sea_level * config.max_height;
+    let reach = |range: f32| (CLIMATE_REACH_RANGES * range / spacing as f32).ceil() as isize;
+    let (spread_reach, wind_reach) = (
+        reach(config.climate_moisture_range),
+        reach(config.climate_wind_range),
+    );
+    let margin = spread_reach.max(wind_reach);
+    let width = nodes_x as isize + 2 * margin;
+    let depth = nodes_z as isize + 2 * margin;
+
+    let mut surface = Vec::with_capacity((width * depth) as usize);
+    let mut water = Vec::with_capacity(surface.capacity());
+    for z in 0..depth {
+        for x in 0..width {
+            let node_height = height(
+                origin.0 + (x - margin) as i32 * spacing,
+                origin.1 + (z - margin) as i32 * spacing,
+            );
+            water.push(node_height <= sea_height);
+            // The wind blows over the surface of the sea rather than its floor.
+            surface.push(max(node_height, sea_height));
+        }
+    }
+
+    // The nodes within reach around a node, nearest first, and the nodes the
+    // wind passes on its way to a node, from the node itself upwind.
+    let mut around = Vec::new();
+    for dz in -spread_reach..=spread_reach {
+        for dx in -spread_reach..=spread_reach {
+            let distance = (dx as f32).hypot(dz as f32);
+            if distance <= spread_reach as f32 {
+                around.push((dz * width + dx, distance));
+            }
+        }
+    }
+    around.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let direction = config.climate_wind_direction.to_radians();
+    let (wind_x, wind_z) = (direction.cos(), direction.sin());
+    let upwind: Vec<(isize, f32)> = (0..=wind_reach)
+        .map(|step| {
+            let dx = (-wind_x * step as f32).round();
+            let dz = (-wind_z * step as f32).round();
+            (dz as isize * width + dx as isize, dx.hypot(dz))
+        })
+        .collect();
+
+    let mut moistures = Vec::with_capacity(nodes_x * nodes_z);
+    for node_z in 0..nodes_z as isize {
+        for node_x in 0..nodes_x as isize {
+            let node = (node_z + margin) * width + node_x + margin;
+            let spread = around
+                .iter()
+                .find(|(offset, _)| water[(node + offset) as usize])
+                .map_or(0.0, |&(_, distance)| {
+                    (-distance * spacing as f32 / config.climate_moisture_range).exp()
+                });
+
+            let mut carried = 0.0;
+            let mut climb = 0.0;
+            let mut downwind = node as usize;
+            for &(offset, distance) in upwind.iter() {
+                let cell = (node + offset) as usize;
+                climb += max(surface[downwind] - surface[cell], 0.0);
+                if water[cell] {
+                    carried = (-distance * spacing as f32 / config.climate_wind_range).exp()
+                        * (-climb / config.max_height * config.climate_rain_shadow).exp();
+                    break;
+                }
+                downwind = cell;
+            }
+            moistures.push(max(spread, carried));
+        }
+    }
+    moistures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{climate_grid, moisture_map, temperature_map};
+    use crate::TerrainConfig;
+
+    #[test]
+    fn cools_towards_the_poles_and_with_altitude() {
+        let config = TerrainConfig {
+            climate_pole_distance: 1000.0,
+            ..TerrainConfig::default()
+        };
+        let sea_height = config.sea_level * config.max_height;
+        let height_map = vec![sea_height; 17 * 17];
+        let equator = temperature_map(&height_map, 16, 16, 0, &config);
+        let north = temperature_map(&height_map, 16, 16, -40, &config);
+        assert_eq!(equator[0], 1.0);
+        assert!(north[0] < equator[16 * 17] && equator[16 * 17] < 1.0);
+        let peak = temperature_map(&vec![config.max_height; 17 * 17], 16, 16, 0, &config);
+        assert!((peak[0] - (1.0 - config.climate_lapse_rate)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn temperature_is_continuous_across_chunks_deeper_than_wide() {
+        let config = TerrainConfig {
+            climate_pole_distance: 1000.0,
+            ..TerrainConfig::default()
+        };
+        let height_map = vec![config.sea_level * config.max_height; 9 * 33];
+        let chunk = temperature_map(&height_map, 8, 32, 3, &config);
+        let below = temperature_map(&height_map, 8, 32, 4, &config);
+        assert_eq!(chunk[32 * 9], below[0]);
+        assert_eq!(below[0], 1.0 - 128.0 / 1000.0);
+    }
+
+    #[test]
+    fn the_wind_carries_moisture_inland_and_mountains_block_it() {
+        let config = TerrainConfig {
+            climate_moisture_range: 4.0,
+            climate_wind_range: 32.0,
+            climate_wind_direction: 0.0,
+            ..TerrainConfig::default()
+        };
+        let land = config.max_height * 0.4;
+        // Sea to the west of x = 0, land everywhere else, and a ridge along
+        // x = 8.
+        let flat = |x: i32, _z: i32| if x < 0 { 0.0 } else { land };
+        let ridge = |x: i32, z: i32| if x == 8 { land * 1.75 } else { flat(x, z) };
+        let (_, flat_moisture) = climate_grid(&flat, (0, 0), 17, 17, 1, &config);
+        let (_, ridge_moisture) = climate_grid(&ridge, (0, 0), 17, 17, 1, &config);
+
+        let at = |moisture: &[f32], x: usize| moisture[8 * 17 + x];
+        assert!(at(&flat_moisture, 0) > at(&flat_moisture, 8));
+        assert!(at(&flat_moisture, 16) > 0.4);
+        assert_eq!(at(&ridge_moisture, 4), at(&flat_moisture, 4));
+        assert!(at(&ridge_moisture, 12) < at(&flat_moisture, 12) * 0.5);
+        assert!(flat_moisture.iter().all(|&m| (0.0..=1.0).contains(&m)));
+    }
+
+    #[test]
+    fn moisture_along_a_chunk_edge_is_the_same_for_both_chunks() {
+        let config = TerrainConfig {
+            climate_wind_direction: 30.0,
+            ..TerrainConfig::default()
+        };
+        let sea_height = config.sea_level * config.max_height;
+        // Hills with the sea in their valleys.
+        let height = |x: i32, z: i32| {
+            sea_height + ((x as f32 / 70.0).sin() + (z as f32 / 50.0).cos()) * 20.0
+        };
+        let chunk =
+            |chunk_x: i32, chunk_z: i32| moisture_map(40, 24, chunk_x, chunk_z, &height, &config);
+        let (chunk, right, below) = (chunk(-1, 2), chunk(0, 2), chunk(-1, 3));
+        for z in 0..=24 {
+            assert_eq!(chunk[z * 41 + 40].to_bits(), right[z * 41].to_bits());
+        }
+        for x in 0..=40 {
+            assert_eq!(chunk[24 * 41 + x].to_bits(), below[x].to_bits());
+        }
+        assert!(chunk
+            .iter()
+            .any(|&moisture| moisture > 0.0 && moisture < 1.0));
+    }
+}
@@ -0,0 +1,632 @@
+use crate::{min, TerrainConfig};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// How colors are mixed between two stops. `Srgb` blends the stored sRGB
+/// values directly, `Linear` blends in linear light, which keeps the middle
+/// of a transition from looking too dark.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorInterpolation {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+/// One stop of a `ColorRamp`. Heights at or below `height` (a fraction of
+/// `max_height`) and above the previous stop blend from the previous stop's
+/// color to `color`, reaching it after `blend` of the way and keeping it
+/// from there on.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub height: f32,
+    pub color: [u8; 3],
+    #[serde(default = "full_blend")]
+    pub blend: f32,
+}
+
+fn full_blend() -> f32 {
+    1.0
+}
+
+const fn stop(height: f32, color: [u8; 3], blend: f32) -> ColorStop {
+    ColorStop {
+        height,
+        color,
+        blend,
+    }
+}
+
+// Width of the transition at each end of a `ColorRule` range, so materials
+// fade in instead of speckling where the terrain is close to a threshold.
+pub(crate) const RULE_HEIGHT_FADE: f32 = 0.02;
+pub(crate) const RULE_SLOPE_FADE: f32 = 4.0;
+pub(crate) const RULE_CURVATURE_FADE: f32 = 0.2;
+
+/// A material painted over the height based colors wherever a vertex falls
+/// inside all of the rule's ranges. Heights are fractions of `max_height`,
+/// slopes are in degrees from horizontal and curvature is the average height
+/// of a vertex's four neighbours minus its own height, in world units, so it
+/// is positive in gullies and at the foot of cliffs and negative on ridges.
+/// Missing bounds are unlimited. Rules are applied in order, each mixing in
+/// its color by up to `strength`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorRule {
+    pub color: [u8; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_height: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_slope: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_slope: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_curvature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_curvature: Option<f32>,
+    #[serde(default = "full_blend")]
+    pub strength: f32,
+}
+
+const ANY: ColorRule = ColorRule {
+    color: [0, 0, 0],
+    min_height: None,
+    max_height: None,
+    min_slope: None,
+    max_slope: None,
+    min_curvature: None,
+    max_curvature: None,
+    strength: 1.0,
+};
+
+// Weight of `value` within [min, max], easing over `fade` around each bound.
+pub(crate) fn range_weight(value: f32, min: Option<f32>, max: Option<f32>, fade: f32) -> f32 {
+    let ease = |t: f32| {
+        let t = t.clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let mut weight = 1.0;
+    if let Some(min) = min {
+        weight *= ease((value - min) / fade + 0.5);
+    }
+    if let Some(max) = max {
+        weight *= ease((max - value) / fade + 0.5);
+    }
+    weight
+}
+
+impl ColorRule {
+    fn weight(&self, height: f32, slope: f32, curvature: f32) -> f32 {
+        self.strength
+            * range_weight(height, self.min_height, self.max_height, RULE_HEIGHT_FADE)
+            * range_weight(slope, self.min_slope, self.max_slope, RULE_SLOPE_FADE)
+            * range_weight(
+                curvature,
+                self.min_curvature,
+                self.max_curvature,
+                RULE_CURVATURE_FADE,
+            )
+    }
+}
+
+pub const COLOR_RAMP_PRESETS: [&str; 7] = [
+    "temperate",
+    "desert",
+    "arctic",
+    "volcanic",
+    "taiga",
+    "savanna",
+    "jungle",
+];
+
+static TEMPERATE: [ColorStop; 7] = [
+    stop(0.093, [201, 178, 99], 0.6),
+    stop(0.164, [164, 155, 98], 0.6),
+    stop(0.243, [164, 155, 98], 0.6),
+    stop(0.374, [120, 127, 160], 1.0),
+    stop(0.571, [90, 91, 98], 1.0),
+    stop(0.846, [193, 198, 214], 1.0),
+    stop(1.0, [235, 236, 240], 1.0),
+];
+
+static TEMPERATE_RULES: [ColorRule; 3] = [
+    // Scree collecting at the foot of cliffs.
+    ColorRule {
+        color: [138, 130, 118],
+        min_height: Some(0.2),
+        min_slope: Some(30.0),
+        max_slope: Some(50.0),
+        min_curvature: Some(0.3),
+        strength: 0.8,
+        ..ANY
+    },
+    // Bare rock on steep faces.
+    ColorRule {
+        color: [98, 94, 90],
+        min_height: Some(0.12),
+        min_slope: Some(55.0),
+        strength: 0.9,
+        ..ANY
+    },
+    // Snow only settles on gentle slopes.
+    ColorRule {
+        color: [242, 244, 250],
+        min_height: Some(0.7),
+        max_slope: Some(40.0),
+        ..ANY
+    },
+];
+
+static DESERT: [ColorStop; 6] = [
+    stop(0.08, [194, 154, 108], 1.0),
+    stop(0.25, [214, 178, 122], 0.8),
+    stop(0.45, [196, 140, 90], 1.0),
+    stop(0.65, [160, 100, 68], 1.0),
+    stop(0.85, [128, 84, 62], 1.0),
+    stop(1.0, [226, 200, 160], 1.0),
+];
+
+static DESERT_RULES: [ColorRule; 2] = [
+    // Sand drifting into hollows.
+    ColorRule {
+        color: [222, 186, 130],
+        max_slope: Some(30.0),
+        min_curvature: Some(0.2),
+        strength: 0.7,
+        ..ANY
+    },
+    // Sandstone cliffs.
+    ColorRule {
+        color: [170, 96, 62],
+        min_slope: Some(52.0),
+        strength: 0.85,
+        ..ANY
+    },
+];
+
+static ARCTIC: [ColorStop; 5] = [
+    stop(0.1, [150, 170, 190], 1.0),
+    stop(0.3, [200, 215, 230], 0.8),
+    stop(0.55, [120, 125, 135], 1.0),
+    stop(0.75, [220, 228, 240], 0.6),
+    stop(1.0, [250, 252, 255], 1.0),
+];
+
+static ARCTIC_RULES: [ColorRule; 2] = [
+    // Rock where the slope is too steep to hold snow.
+    ColorRule {
+        color: [84, 88, 96],
+        min_slope: Some(55.0),
+        strength: 0.9,
+        ..ANY
+    },
+    // Wind-scoured ridges.
+    ColorRule {
+        color: [150, 156, 166],
+        min_height: Some(0.4),
+        max_curvature: Some(-0.4),
+        strength: 0.5,
+        ..ANY
+    },
+];
+
+static VOLCANIC: [ColorStop; 6] = [
+    stop(0.1, [40, 38, 40], 1.0),
+    stop(0.3, [70, 60, 55], 1.0),
+    stop(0.55, [95, 70, 60], 1.0),
+    stop(0.75, [60, 50, 50], 1.0),
+    stop(0.9, [140, 50, 30], 1.0),
+    stop(1.0, [230, 110, 40], 1.0),
+];
+
+static VOLCANIC_RULES: [ColorRule; 2] = [
+    // Ash settling on flat ground.
+    ColorRule {
+        color: [110, 104, 100],
+        max_height: Some(0.75),
+        max_slope: Some(25.0),
+        strength: 0.6,
+        ..ANY
+    },
+    // Glowing lava channels in the upper gullies.
+    ColorRule {
+        color: [220, 80, 20],
+        min_height: Some(0.6),
+        min_curvature: Some(0.6),
+        strength: 0.8,
+        ..ANY
+    },
+];
+
+static TAIGA: [ColorStop; 6] = [
+    stop(0.1, [120, 130, 110], 1.0),
+    stop(0.3, [70, 92, 64], 0.8),
+    stop(0.5, [58, 76, 56], 1.0),
+    stop(0.7, [110, 110, 104], 1.0),
+    stop(0.85, [225, 230, 238], 0.6),
+    stop(1.0, [245, 247, 252], 1.0),
+];
+
+static TAIGA_RULES: [ColorRule; 2] = [
+    // Rock where the conifers can't hold on.
+    ColorRule {
+        color: [96, 94, 92],
+        min_slope: Some(50.0),
+        strength: 0.85,
+        ..ANY
+    },
+    // Snow lying on the gentler upper slopes.
+    ColorRule {
+        color: [236, 240, 248],
+        min_height: Some(0.65),
+        max_slope: Some(35.0),
+        strength: 0.9,
+        ..ANY
+    },
+];
+
+static SAVANNA: [ColorStop; 6] = [
+    stop(0.1, [196, 170, 120], 1.0),
+    stop(0.3, [190, 168, 96], 0.8),
+    stop(0.5, [164, 146, 84], 1.0),
+    stop(0.7, [150, 110, 76], 1.0),
+    stop(0.9, [120, 96, 80], 1.0),
+    stop(1.0, [170, 150, 130], 1.0),
+];
+
+static SAVANNA_RULES: [ColorRule; 2] = [
+    // Greener grass and scrub where water gathers.
+    ColorRule {
+        color: [120, 130, 70],
+        max_slope: Some(25.0),
+        min_curvature: Some(0.3),
+        strength: 0.6,
+        ..ANY
+    },
+    // Red laterite cliffs.
+    ColorRule {
+        color: [160, 84, 52],
+        min_slope: Some(45.0),
+        strength: 0.8,
+        ..ANY
+    },
+];
+
+static JUNGLE: [ColorStop; 6] = [
+    stop(0.1, [170, 150, 100], 1.0),
+    stop(0.25, [40, 96, 44], 0.6),
+    stop(0.5, [30, 80, 36], 1.0),
+    stop(0.75, [52, 84, 48], 1.0),
+    stop(0.9, [90, 96, 84], 1.0),
+    stop(1.0, [120, 124, 116], 1.0),
+];
+
+static JUNGLE_RULES: [ColorRule; 2] = [
+    // Wet rock on steep faces.
+    ColorRule {
+        color: [70, 74, 66],
+        min_slope: Some(55.0),
+        strength: 0.8,
+        ..ANY
+    },
+    // Mud along the gullies of the lowlands.
+    ColorRule {
+        color: [110, 96, 60],
+        max_height: Some(0.5),
+        min_curvature: Some(0.5),
+        strength: 0.5,
+        ..ANY
+    },
+];
+
+/// Maps heights to vertex colors. Can be built from stops, from one of the
+/// `COLOR_RAMP_PRESETS` or from JSON such as
+/// `{"interpolation": "linear", "stops": [{"height": 0.5, "color": [90, 91, 98], "blend": 1.0}]}`.
+/// `rules` then paint materials over those colors based on slope and
+/// curvature.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorRamp {
+    stops: Vec<ColorStop>,
+    #[serde(default)]
+    interpolation: ColorInterpolation,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<ColorRule>,
+}
+
+impl Default for ColorRamp {
+    fn default() -> ColorRamp {
+        ColorRamp::preset("temperate").unwrap()
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ColorRamp {
+    pub fn new(
+        stops: Vec<ColorStop>,
+        interpolation: ColorInterpolation,
+    ) -> Result<ColorRamp, String> {
+        let ramp = ColorRamp {
+            stops,
+            interpolation,
+            rules: Vec::new(),
+        };
+        ramp.validate()?;
+        Ok(ramp)
+    }
+
+    pub fn with_rules(self, rules: Vec<ColorRule>) -> Result<ColorRamp, String> {
+        let ramp = ColorRamp { rules, ..self };
+        ramp.validate()?;
+        Ok(ramp)
+    }
+
+    pub fn preset(name: &str) -> Result<ColorRamp, String> {
+        let (stops, rules, interpolation): (&[ColorStop], &[ColorRule], _) = match name {
+            "temperate" => (&TEMPERATE, &TEMPERATE_RULES, ColorInterpolation::Srgb),
+            "desert" => (&DESERT, &DESERT_RULES, ColorInterpolation::Linear),
+            "arctic" => (&ARCTIC, &ARCTIC_RULES, ColorInterpolation::Linear),
+            "volcanic" => (&VOLCANIC, &VOLCANIC_RULES, ColorInterpolation::Linear),
+            "taiga" => (&TAIGA, &TAIGA_RULES, ColorInterpolation::Linear),
+            "savanna" => (&SAVANNA, &SAVANNA_RULES, ColorInterpolation::Linear),
+            "jungle" => (&JUNGLE, &JUNGLE_RULES, ColorInterpolation::Linear),
+            _ => {
+                return Err(format!(
+                    "unknown color ramp preset {}, expected one of {}",
+                    name,
+                    COLOR_RAMP_PRESETS.join(", ")
+                ))
+            }
+        };
+        ColorRamp::new(stops.to_vec(), interpolation)?.with_rules(rules.to_vec())
+    }
+
+    pub fn from_json(json: &str) -> Result<ColorRamp, String> {
+        let ramp: ColorRamp = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        ramp.validate()?;
+        Ok(ramp)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn stops(&self) -> &[ColorStop] {
+        &self.stops
+    }
+
+    pub fn interpolation(&self) -> ColorInterpolation {
+        self.interpolation
+    }
+
+    pub fn rules(&self) -> &[ColorRule] {
+        &self.rules
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stops.is_empty() {
+            return Err("a color ramp needs at least one stop".to_string());
+        }
+        for (i, stop) in self.stops.iter().enumerate() {
+            if !stop.height.is_finite() {
+                return Err(format!("color stop {} has a non-finite height", i));
+            }
+            if i > 0 && stop.height <= self.stops[i - 1].height {
+                return Err(format!(
+                    "color stop heights must increase, but stop {} is at {} after {}",
+                    i,
+                    stop.height,
+                    self.stops[i - 1].height
+                ));
+            }
+            if !(stop.blend > 0.0 && stop.blend <= 1.0) {
+                return Err(format!(
+                    "color stop {} has blend {}, expected a value in (0, 1]",
+                    i, stop.blend
+                ));
+            }
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            let ranges = [
+                ("height", rule.min_height, rule.max_height),
+                ("slope", rule.min_slope, rule.max_slope),
+                ("curvature", rule.min_curvature, rule.max_curvature),
+            ];
+            for &(name, min, max) in ranges.iter() {
+                if min.into_iter().chain(max).any(|bound| !bound.is_finite()) {
+                    return Err(format!("color rule {} has a non-finite {} bound", i, name));
+                }
+                if let (Some(min), Some(max)) = (min, max) {
+                    if min > max {
+                        return Err(format!(
+                            "color rule {} has min_{} {} above max_{} {}",
+                            i, name, min, name, max
+                        ));
+                    }
+                }
+            }
+            if !(rule.strength > 0.0 && rule.strength <= 1.0) {
+                return Err(format!(
+                    "color rule {} has strength {}, expected a value in (0, 1]",
+                    i, rule.strength
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Color of a height given as a fraction of `max_height`. Heights past
+    /// the last stop keep its color.
+    pub fn color_at(&self, height: f32) -> [f32; 3] {
+        let channels = |stop: &ColorStop| {
+            [
+                stop.color[0] as f32 / 255.0,
+                stop.color[1] as f32 / 255.0,
+                stop.color[2] as f32 / 255.0,
+            ]
+        };
+        let index = match self.stops.iter().position(|stop| height <= stop.height) {
+            Some(0) => return channels(&self.stops[0]),
+            Some(index) => index,
+            None => return channels(&self.stops[self.stops.len() - 1]),
+        };
+        let stop = &self.stops[index];
+        let prev_stop = &self.stops[index - 1];
+        let blend = min(
+            (height - prev_stop.height) / ((stop.height - prev_stop.height) * stop.blend),
+            1.0,
+        );
+        self.mix(channels(prev_stop), channels(stop), blend)
+    }
+
+    /// `color_at` with the rules applied. `slope` is in degrees.
+    pub fn surface_color(&self, height: f32, slope: f32, curvature: f32) -> [f32; 3] {
+        let mut color = self.color_at(height);
+        for rule in self.rules.iter() {
+            let weight = rule.weight(height, slope, curvature);
+            if weight > 0.0 {
+                let rule_color = [
+                    rule.color[0] as f32 / 255.0,
+                    rule.color[1] as f32 / 255.0,
+                    rule.color[2] as f32 / 255.0,
+                ];
+                color = self.mix(color, rule_color, weight);
+            }
+        }
+        color
+    }
+
+    fn mix(&self, from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+        let mut color = [0.0; 3];
+        for channel in 0..3 {
+            color[channel] = match self.interpolation {
+                ColorInterpolation::Srgb => from[channel] + (to[channel] - from[channel]) * t,
+                ColorInterpolation::Linear => {
+                    let from = srgb_to_linear(from[channel]);
+                    let to = srgb_to_linear(to[channel]);
+                    linear_to_srgb(from + (to - from) * t)
+                }
+            };
+        }
+        color
+    }
+}
+
+/// Colors from the ramp, using the vertex normals for the slope of each
+/// vertex.
+pub fn build_colors(
+    height_map: &[f32],
+    normals: &[f32],
+    curvatures: &[f32],
+    ramp: &ColorRamp,
+    config: &TerrainConfig,
+) -> Vec<f32> {
+    let mut colors = Vec::with_capacity(height_map.len() * 3);
+    for (i, &height) in height_map.iter().enumerate() {
+        let slope = normals[i * 3 + 1].clamp(-1.0, 1.0).acos().to_degrees();
+        colors.extend_from_slice(&ramp.surface_color(
+            height / config.max_height,
+            slope,
+            curvatures[i],
+        ));
+    }
+    colors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorInterpolation, ColorRamp, ColorRule, ColorStop, ANY, COLOR_RAMP_PRESETS};
+
+    fn ramp(interpolation: ColorInterpolation) -> ColorRamp {
+        ColorRamp::new(
+            vec![
+                ColorStop {
+                    height: 0.2,
+                    color: [0, 0, 0],
+                    blend: 1.0,
+                },
+                ColorStop {
+                    height: 0.6,
+                    color: [255, 255, 255],
+                    blend: 0.5,
+                },
+            ],
+            interpolation,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn interpolates_between_stops() {
+        let srgb = ramp(ColorInterpolation::Srgb);
+        assert_eq!(srgb.color_at(0.1), [0.0; 3]);
+        assert!((srgb.color_at(0.3)[0] - 0.5).abs() < 1e-6);
+        assert_eq!(srgb.color_at(0.5), [1.0; 3]);
+        assert_eq!(srgb.color_at(2.0), [1.0; 3]);
+
+        let linear = ramp(ColorInterpolation::Linear);
+        let middle = linear.color_at(0.3)[0];
+        assert!((middle - 0.735).abs() < 0.001, "{}", middle);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        for name in COLOR_RAMP_PRESETS.iter() {
+            let ramp = ColorRamp::preset(name).unwrap();
+            assert_eq!(ColorRamp::from_json(&ramp.to_json()).unwrap(), ramp);
+        }
+        let ramp =
+            ColorRamp::from_json(r#"{"stops": [{"height": 1.0, "color": [1, 2, 3]}]}"#).unwrap();
+        assert_eq!(ramp.interpolation(), ColorInterpolation::Srgb);
+        assert_eq!(ramp.stops()[0].blend, 1.0);
+
+        assert!(ColorRamp::from_json(r#"{"stops": []}"#).is_err());
+        assert!(ColorRamp::from_json(
+            r#"{"stops": [{"height": 1.0, "color": [1, 2, 3]}],
+                "rules": [{"color": [0, 0, 0], "min_slope": 40, "max_slope": 30}]}"#
+        )
+        .is_err());
+        assert!(ColorRamp::preset("lunar").is_err());
+    }
+
+    #[test]
+    fn applies_rules_by_slope_and_curvature() {
+        let rock = ColorRule {
+            color: [255, 0, 0],
+            min_slope: Some(40.0),
+            ..ANY
+        };
+        let scree = ColorRule {
+            color: [0, 255, 0],
+            max_slope: Some(30.0),
+            min_curvature: Some(0.5),
+            strength: 0.5,
+            ..ANY
+        };
+        let ramp = ramp(ColorInterpolation::Srgb)
+            .with_rules(vec![rock, scree])
+            .unwrap();
+        assert_eq!(ramp.surface_color(0.1, 10.0, 0.0), [0.0; 3]);
+        assert_eq!(ramp.surface_color(0.1, 60.0, 0.0), [1.0, 0.0, 0.0]);
+        assert_eq!(ramp.surface_color(0.1, 10.0, 2.0), [0.0, 0.5, 0.0]);
+        let fading = ramp.surface_color(0.1, 40.0, 0.0)[0];
+        assert!(fading > 0.0 && fading < 1.0);
+    }
+}
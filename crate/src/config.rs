@@ -0,0 +1,520 @@
+use crate::height::NoiseFunction;
+use crate::lakes::LakeMode;
+use crate::rivers::FlowRouting;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+const CHUNK_WIDTH: u32 = 1024;
+const CHUNK_DEPTH: u32 = 1024;
+const MAX_CHUNK_SIZE: u32 = 8192;
+const MAX_HEIGHT: f32 = 512.0;
+const OCTAVES: u32 = 5;
+const PERSISTENCE: f32 = 0.25;
+const LACUNARITY: f32 = 2.5;
+const FINENESS: f32 = 512.0;
+const NOISE_SLOPE: f32 = 0.84;
+const NOISE_FUNCTION: NoiseFunction = NoiseFunction::SuperSimplex;
+const WARP_LEVELS: u32 = 0;
+const WARP_AMPLITUDE: f32 = 80.0;
+const WARP_FREQUENCY: f32 = 1.0;
+const WARP_SEED_OFFSET: u32 = 1;
+const CONTINENT_FINENESS: f32 = 0.0;
+const CONTINENT_LAND_RATIO: f32 = 0.4;
+const CONTINENT_COAST_WIDTH: f32 = 0.1;
+const CONTINENT_COAST_EXPONENT: f32 = 1.0;
+const CONTINENT_AMPLITUDE: f32 = 0.3;
+const CONTINENT_SEED_OFFSET: u32 = 5;
+const EROSION_DROPS_PER_CELL: f32 = 1.2;
+const EROSION_EDGE_DAMP_MIN_DISTANCE: f32 = 2.0;
+const EROSION_EDGE_DAMP_MAX_DISTANCE: f32 = 10.0;
+const EROSION_EDGE_DAMP_STRENGTH: f32 = 3.0;
+const EROSION_INERTIA: f32 = 0.05;
+const EROSION_SEDIMENT_CAPACITY_FACTOR: f32 = 1.0;
+const EROSION_MIN_SEDIMENT_CAPACITY: f32 = 0.1;
+const EROSION_ERODE_SPEED: f32 = 0.3;
+const EROSION_DEPOSIT_SPEED: f32 = 0.5;
+const EROSION_EVAPORATE_SPEED: f32 = 0.01;
+const EROSION_GRAVITY: f32 = 0.2;
+const EROSION_MAX_DROPLET_LIFETIME: u32 = 512;
+const EROSION_STOP_HEIGHT_START: f32 = 0.37;
+const EROSION_STOP_HEIGHT_END: f32 = 0.34;
+const EROSION_INITIAL_WATER_VOLUME: f32 = 1.0;
+const EROSION_INITIAL_SPEED: f32 = 4.0;
+const EROSION_APRON: u32 = 0;
+const EROSION_DROPLET_MAPS: bool = false;
+const THERMAL_EROSION_ITERATIONS: u32 = 0;
+const THERMAL_EROSION_TALUS_ANGLE: f32 = 50.0;
+const THERMAL_EROSION_RATE: f32 = 0.5;
+const THERMAL_EROSION_BEFORE_HYDRAULIC: bool = false;
+const PIPE_EROSION_ITERATIONS: u32 = 0;
+const PIPE_EROSION_TIME_STEP: f32 = 0.02;
+const PIPE_EROSION_RAIN_RATE: f32 = 0.5;
+const PIPE_EROSION_GRAVITY: f32 = 9.81;
+const PIPE_EROSION_SEDIMENT_CAPACITY: f32 = 1.0;
+const PIPE_EROSION_DISSOLVE_SPEED: f32 = 0.5;
+const PIPE_EROSION_DEPOSIT_SPEED: f32 = 1.0;
+const PIPE_EROSION_EVAPORATION: f32 = 0.1;
+const RIVER_CATCHMENT_THRESHOLD: f32 = 0.0;
+const RIVER_FLOW_ROUTING: FlowRouting = FlowRouting::D8;
+const RIVER_WIDTH_FACTOR: f32 = 0.05;
+const RIVER_DEPTH_RATIO: f32 = 0.1;
+const RIVER_CARVE: bool = false;
+const SEA_LEVEL: f32 = 0.37;
+const LAKE_MODE: LakeMode = LakeMode::Off;
+const LAKE_MIN_DEPTH: f32 = 0.5;
+const CLIMATE_POLE_DISTANCE: f32 = 32768.0;
+const CLIMATE_LAPSE_RATE: f32 = 0.5;
+const CLIMATE_MOISTURE_RANGE: f32 = 48.0;
+const CLIMATE_WIND_DIRECTION: f32 = 0.0;
+const CLIMATE_WIND_RANGE: f32 = 192.0;
+const CLIMATE_RAIN_SHADOW: f32 = 4.0;
+const BIOME_BLEND_RADIUS: f32 = 32.0;
+
+/// Every tunable generation parameter.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainConfig {
+    /// Cells along x in every chunk, a power of two.
+    pub chunk_width: u32,
+    /// Cells along z in every chunk, a power of two.
+    pub chunk_depth: u32,
+    /// Height of the highest possible terrain, in world units.
+    pub max_height: f32,
+    /// Octaves of noise summed for the base heights.
+    pub octaves: u32,
+    /// Amplitude of each octave relative to the one before.
+    pub persistence: f32,
+    /// Frequency of each octave relative to the one before.
+    pub lacunarity: f32,
+    /// World units per unit of the base noise.
+    pub fineness: f32,
+    /// Exponent every octave's noise, in [0, 1], is raised to.
+    pub noise_slope: f32,
+    /// Noise sampled by every octave.
+    pub noise_function: NoiseFunction,
+    /// Domain warp levels: 0 (off), 1 or 2.
+    pub warp_levels: u32,
+    /// How far the domain warp moves features, in world units.
+    pub warp_amplitude: f32,
+    /// Frequency of the warp noise relative to the base noise.
+    pub warp_frequency: f32,
+    /// Seed of the warp noise, past the world seed.
+    pub warp_seed_offset: u32,
+    /// World units per unit of the continent noise; 0 means no continents.
+    pub continent_fineness: f32,
+    /// Fraction of the world raised into continents rather than oceans.
+    pub continent_land_ratio: f32,
+    /// Width of the coast, in continentalness units.
+    pub continent_coast_width: f32,
+    /// Coast profile: below 1 rises into cliffs, above 1 over flat beaches.
+    pub continent_coast_exponent: f32,
+    /// Continent raise and ocean drop, a fraction of `max_height`.
+    pub continent_amplitude: f32,
+    /// Seed of the continent noise, past the world seed.
+    pub continent_seed_offset: u32,
+    /// Droplets simulated per cell of the chunk.
+    pub erosion_drops_per_cell: f32,
+    /// Distance from the chunk edge, in cells, at which droplets stop.
+    pub erosion_edge_damp_min_distance: f32,
+    /// Distance from the chunk edge, in cells, within which erosion is damped.
+    pub erosion_edge_damp_max_distance: f32,
+    /// How sharply erosion is damped between the two distances.
+    pub erosion_edge_damp_strength: f32,
+    /// How much a droplet keeps its direction instead of following the slope.
+    pub erosion_inertia: f32,
+    /// Sediment a droplet can carry per unit of speed, water and slope.
+    pub erosion_sediment_capacity_factor: f32,
+    /// Sediment a droplet can always carry, even on flat ground.
+    pub erosion_min_sediment_capacity: f32,
+    /// Fraction of its free capacity a droplet erodes at every step.
+    pub erosion_erode_speed: f32,
+    /// Fraction of its excess sediment a droplet deposits at every step.
+    pub erosion_deposit_speed: f32,
+    /// Fraction of its water a droplet loses at every step.
+    pub erosion_evaporate_speed: f32,
+    /// Acceleration of droplets down slopes.
+    pub erosion_gravity: f32,
+    /// Steps a droplet lives for at most.
+    pub erosion_max_droplet_lifetime: u32,
+    /// Height, a fraction of `max_height`, below which erosion fades out.
+    pub erosion_stop_height_start: f32,
+    /// Height, a fraction of `max_height`, below which there is no erosion.
+    pub erosion_stop_height_end: f32,
+    /// Water every droplet starts with.
+    pub erosion_initial_water_volume: f32,
+    /// Speed every droplet starts with.
+    pub erosion_initial_speed: f32,
+    /// Cells simulated past each chunk edge; above 0 erodes seamlessly.
+    pub erosion_apron: u32,
+    /// Record the droplets' water flow, deposition and erosion per cell.
+    pub erosion_droplet_maps: bool,
+    /// Thermal erosion passes, none by default.
+    pub thermal_erosion_iterations: u32,
+    /// Slope, in degrees, above which thermal erosion moves material.
+    pub thermal_erosion_talus_angle: f32,
+    /// Fraction of the material above the talus angle moved every pass.
+    pub thermal_erosion_rate: f32,
+    /// Run thermal erosion before the droplets instead of after them.
+    pub thermal_erosion_before_hydraulic: bool,
+    /// Pipe erosion timesteps run after the droplets, none by default.
+    pub pipe_erosion_iterations: u32,
+    /// Simulated time per pipe erosion timestep.
+    pub pipe_erosion_time_step: f32,
+    /// Rain falling on every cell, in world units per unit of time.
+    pub pipe_erosion_rain_rate: f32,
+    /// Acceleration of the water flowing between cells.
+    pub pipe_erosion_gravity: f32,
+    /// Sediment the water can carry per unit of speed and slope.
+    pub pipe_erosion_sediment_capacity: f32,
+    /// How fast the water dissolves the terrain below its capacity.
+    pub pipe_erosion_dissolve_speed: f32,
+    /// How fast the water deposits sediment above its capacity.
+    pub pipe_erosion_deposit_speed: f32,
+    /// Fraction of the water evaporating per unit of time.
+    pub pipe_erosion_evaporation: f32,
+    /// Cells draining through a cell that make it a river; 0 for none.
+    pub river_catchment_threshold: f32,
+    /// How water is routed between cells to trace rivers.
+    pub river_flow_routing: FlowRouting,
+    /// Width of a river over the square root of its catchment.
+    pub river_width_factor: f32,
+    /// Depth of a carved river bed relative to its width.
+    pub river_depth_ratio: f32,
+    /// Cut the river beds into the terrain.
+    pub river_carve: bool,
+    /// Height of the sea, a fraction of `max_height`.
+    pub sea_level: f32,
+    /// What happens to the depressions left in the eroded terrain.
+    pub lake_mode: LakeMode,
+    /// Depth, in world units, a depression needs to become a lake.
+    pub lake_min_depth: f32,
+    /// Distance, in world units, from the equator at z = 0 to the poles.
+    pub climate_pole_distance: f32,
+    /// How much colder it gets from sea level to `max_height`.
+    pub climate_lapse_rate: f32,
+    /// Cells around the sea over which its moisture fades.
+    pub climate_moisture_range: f32,
+    /// Wind direction, in degrees from the x axis towards the z axis.
+    pub climate_wind_direction: f32,
+    /// Cells downwind of the sea over which the moisture it carries fades.
+    pub climate_wind_range: f32,
+    /// Moisture lost by air climbing `max_height`, as e^-`climate_rain_shadow`.
+    pub climate_rain_shadow: f32,
+    /// Cells over which biome height profiles blend into their neighbours.
+    pub biome_blend_radius: f32,
+}
+
+pub const DEFAULT_CONFIG: TerrainConfig = TerrainConfig {
+    chunk_width: CHUNK_WIDTH,
+    chunk_depth: CHUNK_DEPTH,
+    max_height: MAX_HEIGHT,
+    octaves: OCTAVES,
+    persistence: PERSISTENCE,
+    lacunarity: LACUNARITY,
+    fineness: FINENESS,
+    noise_slope: NOISE_SLOPE,
+    noise_function: NOISE_FUNCTION,
+    warp_levels: WARP_LEVELS,
+    warp_amplitude: WARP_AMPLITUDE,
+    warp_frequency: WARP_FREQUENCY,
+    warp_seed_offset: WARP_SEED_OFFSET,
+    continent_fineness: CONTINENT_FINENESS,
+    continent_land_ratio: CONTINENT_LAND_RATIO,
+    continent_coast_width: CONTINENT_COAST_WIDTH,
+    continent_coast_exponent: CONTINENT_COAST_EXPONENT,
+    continent_amplitude: CONTINENT_AMPLITUDE,
+    continent_seed_offset: CONTINENT_SEED_OFFSET,
+    erosion_drops_per_cell: EROSION_DROPS_PER_CELL,
+    erosion_edge_damp_min_distance: EROSION_EDGE_DAMP_MIN_DISTANCE,
+    erosion_edge_damp_max_distance: EROSION_EDGE_DAMP_MAX_DISTANCE,
+    erosion_edge_damp_strength: EROSION_EDGE_DAMP_STRENGTH,
+    erosion_inertia: EROSION_INERTIA,
+    erosion_sediment_capacity_factor: EROSION_SEDIMENT_CAPACITY_FACTOR,
+    erosion_min_sediment_capacity: EROSION_MIN_SEDIMENT_CAPACITY,
+    erosion_erode_speed: EROSION_ERODE_SPEED,
+    erosion_deposit_speed: EROSION_DEPOSIT_SPEED,
+    erosion_evaporate_speed: EROSION_EVAPORATE_SPEED,
+    erosion_gravity: EROSION_GRAVITY,
+    erosion_max_droplet_lifetime: EROSION_MAX_DROPLET_LIFETIME,
+    erosion_stop_height_start: EROSION_STOP_HEIGHT_START,
+    erosion_stop_height_end: EROSION_STOP_HEIGHT_END,
+    erosion_initial_water_volume: EROSION_INITIAL_WATER_VOLUME,
+    erosion_initial_speed: EROSION_INITIAL_SPEED,
+    erosion_apron: EROSION_APRON,
+    erosion_droplet_maps: EROSION_DROPLET_MAPS,
+    thermal_erosion_iterations: THERMAL_EROSION_ITERATIONS,
+    thermal_erosion_talus_angle: THERMAL_EROSION_TALUS_ANGLE,
+    thermal_erosion_rate: THERMAL_EROSION_RATE,
+    thermal_erosion_before_hydraulic: THERMAL_EROSION_BEFORE_HYDRAULIC,
+    pipe_erosion_iterations: PIPE_EROSION_ITERATIONS,
+    pipe_erosion_time_step: PIPE_EROSION_TIME_STEP,
+    pipe_erosion_rain_rate: PIPE_EROSION_RAIN_RATE,
+    pipe_erosion_gravity: PIPE_EROSION_GRAVITY,
+    pipe_erosion_sediment_capacity: PIPE_EROSION_SEDIMENT_CAPACITY,
+    pipe_erosion_dissolve_speed: PIPE_EROSION_DISSOLVE_SPEED,
+    pipe_erosion_deposit_speed: PIPE_EROSION_DEPOSIT_SPEED,
+    pipe_erosion_evaporation: PIPE_EROSION_EVAPORATION,
+    river_catchment_threshold: RIVER_CATCHMENT_THRESHOLD,
+    river_flow_routing: RIVER_FLOW_ROUTING,
+    river_width_factor: RIVER_WIDTH_FACTOR,
+    river_depth_ratio: RIVER_DEPTH_RATIO,
+    river_carve: RIVER_CARVE,
+    sea_level: SEA_LEVEL,
+    lake_mode: LAKE_MODE,
+    lake_min_depth: LAKE_MIN_DEPTH,
+    climate_pole_distance: CLIMATE_POLE_DISTANCE,
+    climate_lapse_rate: CLIMATE_LAPSE_RATE,
+    climate_moisture_range: CLIMATE_MOISTURE_RANGE,
+    climate_wind_direction: CLIMATE_WIND_DIRECTION,
+    climate_wind_range: CLIMATE_WIND_RANGE,
+    climate_rain_shadow: CLIMATE_RAIN_SHADOW,
+    biome_blend_radius: BIOME_BLEND_RADIUS,
+};
+
+impl Default for TerrainConfig {
+    fn default() -> TerrainConfig {
+        DEFAULT_CONFIG
+    }
+}
+
+fn check(condition: bool, message: &str) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(message.to_string())
+    }
+}
+
+fn is_chunk_size(value: u32) -> bool {
+    (2..=MAX_CHUNK_SIZE).contains(&value) && value.is_power_of_two()
+}
+
+fn is_fraction(value: f32) -> bool {
+    (0.0..=1.0).contains(&value)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl TerrainConfig {
+    pub fn height_map_array_length(&self) -> usize {
+        ((self.chunk_width + 1) * (self.chunk_depth + 1)) as usize
+    }
+
+    pub fn vertex_array_length(&self) -> usize {
+        self.height_map_array_length() * 3
+    }
+
+    pub fn indices_array_length(&self) -> usize {
+        (self.chunk_width * self.chunk_depth * 6) as usize
+    }
+}
+
+impl TerrainConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        check(
+            is_chunk_size(self.chunk_width),
+            "chunk_width must be a power of two between 2 and 8192",
+        )?;
+        check(
+            is_chunk_size(self.chunk_depth),
+            "chunk_depth must be a power of two between 2 and 8192",
+        )?;
+        check(self.max_height > 0.0, "max_height must be positive")?;
+        check(
+            (1..=16).contains(&self.octaves),
+            "octaves must be between 1 and 16",
+        )?;
+        check(
+            self.persistence > 0.0 && self.persistence <= 1.0,
+            "persistence must be in (0, 1]",
+        )?;
+        check(self.lacunarity >= 1.0, "lacunarity must be at least 1")?;
+        check(self.fineness > 0.0, "fineness must be positive")?;
+        check(self.noise_slope > 0.0, "noise_slope must be positive")?;
+        check(self.warp_levels <= 2, "warp_levels must be at most 2")?;
+        check(
+            self.warp_amplitude >= 0.0,
+            "warp_amplitude must not be negative",
+        )?;
+        check(self.warp_frequency > 0.0, "warp_frequency must be positive")?;
+        check(
+            self.continent_fineness >= 0.0,
+            "continent_fineness must not be negative",
+        )?;
+        check(
+            is_fraction(self.continent_land_ratio),
+            "continent_land_ratio must be in [0, 1]",
+        )?;
+        check(
+            self.continent_coast_width > 0.0,
+            "continent_coast_width must be positive",
+        )?;
+        check(
+            self.continent_coast_exponent > 0.0,
+            "continent_coast_exponent must be positive",
+        )?;
+        check(
+            is_fraction(self.continent_amplitude),
+            "continent_amplitude must be in [0, 1]",
+        )?;
+        check(
+            self.erosion_drops_per_cell >= 0.0,
+            "erosion_drops_per_cell must not be negative",
+        )?;
+        check(
+            self.erosion_edge_damp_min_distance >= 2.0,
+            "erosion_edge_damp_min_distance must be at least 2",
+        )?;
+        check(
+            self.erosion_edge_damp_max_distance > self.erosion_edge_damp_min_distance,
+            "erosion_edge_damp_max_distance must be greater than erosion_edge_damp_min_distance",
+        )?;
+        check(
+            self.erosion_edge_damp_strength > 0.0,
+            "erosion_edge_damp_strength must be positive",
+        )?;
+        check(
+            is_fraction(self.erosion_inertia),
+            "erosion_inertia must be in [0, 1]",
+        )?;
+        check(
+            self.erosion_sediment_capacity_factor >= 0.0,
+            "erosion_sediment_capacity_factor must not be negative",
+        )?;
+        check(
+            self.erosion_min_sediment_capacity >= 0.0,
+            "erosion_min_sediment_capacity must not be negative",
+        )?;
+        check(
+            is_fraction(self.erosion_erode_speed),
+            "erosion_erode_speed must be in [0, 1]",
+        )?;
+        check(
+            is_fraction(self.erosion_deposit_speed),
+            "erosion_deposit_speed must be in [0, 1]",
+        )?;
+        check(
+            is_fraction(self.erosion_evaporate_speed),
+            "erosion_evaporate_speed must be in [0, 1]",
+        )?;
+        check(
+            self.erosion_gravity >= 0.0,
+            "erosion_gravity must not be negative",
+        )?;
+        check(
+            self.erosion_max_droplet_lifetime >= 1,
+            "erosion_max_droplet_lifetime must be at least 1",
+        )?;
+        check(
+            is_fraction(self.erosion_stop_height_start),
+            "erosion_stop_height_start must be in [0, 1]",
+        )?;
+        check(
+            is_fraction(self.erosion_stop_height_end),
+            "erosion_stop_height_end must be in [0, 1]",
+        )?;
+        check(
+            self.erosion_stop_height_start > self.erosion_stop_height_end,
+            "erosion_stop_height_start must be greater than erosion_stop_height_end",
+        )?;
+        check(
+            self.erosion_initial_water_volume > 0.0,
+            "erosion_initial_water_volume must be positive",
+        )?;
+        check(
+            self.erosion_initial_speed >= 0.0,
+            "erosion_initial_speed must not be negative",
+        )?;
+        check(
+            self.thermal_erosion_talus_angle > 0.0 && self.thermal_erosion_talus_angle < 90.0,
+            "thermal_erosion_talus_angle must be between 0 and 90 degrees",
+        )?;
+        check(
+            self.thermal_erosion_rate > 0.0 && self.thermal_erosion_rate <= 1.0,
+            "thermal_erosion_rate must be in (0, 1]",
+        )?;
+        check(
+            self.pipe_erosion_time_step > 0.0 && self.pipe_erosion_time_step <= 0.1,
+            "pipe_erosion_time_step must be in (0, 0.1]",
+        )?;
+        check(
+            self.pipe_erosion_rain_rate >= 0.0,
+            "pipe_erosion_rain_rate must not be negative",
+        )?;
+        check(
+            self.pipe_erosion_gravity > 0.0,
+            "pipe_erosion_gravity must be positive",
+        )?;
+        check(
+            self.pipe_erosion_sediment_capacity >= 0.0,
+            "pipe_erosion_sediment_capacity must not be negative",
+        )?;
+        check(
+            is_fraction(self.pipe_erosion_dissolve_speed * self.pipe_erosion_time_step),
+            "pipe_erosion_dissolve_speed times pipe_erosion_time_step must be in [0, 1]",
+        )?;
+        check(
+            is_fraction(self.pipe_erosion_deposit_speed * self.pipe_erosion_time_step),
+            "pipe_erosion_deposit_speed times pipe_erosion_time_step must be in [0, 1]",
+        )?;
+        check(
+            is_fraction(self.pipe_erosion_evaporation * self.pipe_erosion_time_step),
+            "pipe_erosion_evaporation times pipe_erosion_time_step must be in [0, 1]",
+        )?;
+        check(
+            self.river_catchment_threshold >= 0.0,
+            "river_catchment_threshold must not be negative",
+        )?;
+        check(
+            self.river_width_factor > 0.0,
+            "river_width_factor must be positive",
+        )?;
+        check(
+            self.river_depth_ratio >= 0.0,
+            "river_depth_ratio must not be negative",
+        )?;
+        check(is_fraction(self.sea_level), "sea_level must be in [0, 1]")?;
+        check(
+            self.lake_min_depth >= 0.0,
+            "lake_min_depth must not be negative",
+        )?;
+        check(
+            self.climate_pole_distance > 0.0,
+            "climate_pole_distance must be positive",
+        )?;
+        check(
+            self.climate_lapse_rate >= 0.0,
+            "climate_lapse_rate must not be negative",
+        )?;
+        check(
+            self.climate_moisture_range > 0.0,
+            "climate_moisture_range must be positive",
+        )?;
+        check(
+            self.climate_wind_direction.is_finite(),
+            "climate_wind_direction must be finite",
+        )?;
+        check(
+            self.climate_wind_range > 0.0,
+            "climate_wind_range must be positive",
+        )?;
+        check(
+            self.climate_rain_shadow >= 0.0,
+            "climate_rain_shadow must not be negative",
+        )?;
+        check(
+            self.biome_blend_radius >= 0.0,
+            "biome_blend_radius must not be negative",
+        )?;
+        if self.erosion_apron > 0 {
+            check(
+                self.erosion_apron as f32 >= self.erosion_edge_damp_max_distance,
+                "erosion_apron must be at least erosion_edge_damp_max_distance",
+            )?;
+            check(
+                self.erosion_apron <= self.chunk_width.min(self.chunk_depth) / 2,
+                "erosion_apron must be at most half the chunk width and depth",
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use crate::pipe::erode_pipe;
+use crate::random::Rng;
+use crate::thermal::erode_thermal;
+use crate::{max, min, TerrainConfig};
+
+const EROSION_KERNEL_RADIUS: i32 = 2;
+const EROSION_KERNEL_ARRAY_SIZE: usize = 25;
+type ErosionKernelArray = [f32; EROSION_KERNEL_ARRAY_SIZE];
+static EROSION_KERNEL: ErosionKernelArray = [
+    0.003765, 0.015019, 0.023792, 0.015019, 0.003765, 0.015019, 0.059912, 0.094907, 0.059912,
+    0.015019, 0.023792, 0.094907, 0.150342, 0.094907, 0.023792, 0.015019, 0.059912, 0.094907,
+    0.059912, 0.015019, 0.003765, 0.015019, 0.023792, 0.015019, 0.003765,
+];
+
+#[derive(Clone, Copy)]
+pub enum ErosionField {
+    Water = 0,
+    Sediment = 1,
+    Flow = 2,
+    Deposition = 3,
+    ErosionDepth = 4,
+}
+
+pub const EROSION_FIELD_COUNT: usize = 5;
+
+/// Per-cell results of erosion besides the heights, laid out like the height
+/// map. A field is empty when the stage producing it did not run.
+#[derive(Default)]
+pub struct ErosionFields {
+    maps: [Vec<f32>; EROSION_FIELD_COUNT],
+}
+
+impl ErosionFields {
+    pub fn get_mut(&mut self, field: ErosionField) -> &mut Vec<f32> {
+        &mut self.maps[field as usize]
+    }
+
+    pub fn take(&mut self, field: ErosionField) -> Vec<f32> {
+        std::mem::take(&mut self.maps[field as usize])
+    }
+
+    pub fn maps(&self) -> &[Vec<f32>; EROSION_FIELD_COUNT] {
+        &self.maps
+    }
+
+    pub fn maps_mut(&mut self) -> &mut [Vec<f32>; EROSION_FIELD_COUNT] {
+        &mut self.maps
+    }
+}
+
+/// Runs the hydraulic erosion droplets, then the grid based pipe model if
+/// enabled, with the thermal erosion pass before or after both.
+pub fn erode(
+    height_map: &mut [f32],
+    width: u32,
+    depth: u32,
+    rng: &mut Rng,
+    config: &TerrainConfig,
+) -> ErosionFields {
+    let mut fields = ErosionFields::default();
+    if config.thermal_erosion_before_hydraulic {
+        erode_thermal(height_map, width, depth, config);
+    }
+    if config.erosion_droplet_maps {
+        let before = height_map.to_vec();
+        let mut flow = vec![0.0; height_map.len()];
+        erode_hydraulic(height_map, width, depth, rng, config, Some(&mut flow));
+        let (deposition, erosion_depth) = height_map
+            .iter()
+            .zip(before)
+            .map(|(after, before)| (max(after - before, 0.0), max(before - after, 0.0)))
+            .unzip();
+        *fields.get_mut(ErosionField::Flow) = flow;
+        *fields.get_mut(ErosionField::Deposition) = deposition;
+        *fields.get_mut(ErosionField::ErosionDepth) = erosion_depth;
+    } else {
+        erode_hydraulic(height_map, width, depth, rng, config, None);
+    }
+    if config.pipe_erosion_iterations > 0 {
+        let (water, sediment) = erode_pipe(height_map, width, depth, config);
+        *fields.get_mut(ErosionField::Water) = water;
+        *fields.get_mut(ErosionField::Sediment) = sediment;
+    }
+    if !config.thermal_erosion_before_hydraulic {
+        erode_thermal(height_map, width, depth, config);
+    }
+    fields
+}
+
+fn erode_hydraulic(
+    height_map: &mut [f32],
+    width: u32,
+    depth: u32,
+    rng: &mut Rng,
+    config: &TerrainConfig,
+    mut flow: Option<&mut [f32]>,
+) {
+    let drops_count = (config.erosion_drops_per_cell * width as f32 * depth as f32).floor() as u32;
+
+    for _ in 0..drops_count {
+        trace(height_map, width, depth, rng, config, flow.as_deref_mut())
+    }
+}
+
+// Based off https://jobtalle.com/simulating_hydraulic_erosion.html
+// MIT License
+// Copyright (c) 2020 Job Talle
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+// When `flow` is given, the droplet adds its water volume to it at every step.
+fn trace(
+    height_map: &mut [f32],
+    width: u32,
+    depth: u32,
+    rng: &mut Rng,
+    config: &TerrainConfig,
+    mut flow: Option<&mut [f32]>,
+) {
+    fn get_height_interpolated(x: f32, z: f32, height_map: &[f32], width: u32) -> f32 {
+        let floor_x = x.floor() as usize;
+        let floor_z = z.floor() as usize;
+
+        let grid_offset_x = x - floor_x as f32;
+        let grid_offset_z = z - floor_z as f32;
+
+        let height_top_left = height_map[floor_z * (width as usize + 1) + floor_x];
+        let height_top_right = height_map[floor_z * (width as usize + 1) + (floor_x + 1)];
+        let height_bottom_left = height_map[(floor_z + 1) * (width as usize + 1) + floor_x];
+        let height_bottom_right = height_map[(floor_z + 1) * (width as usize + 1) + (floor_x + 1)];
+
+        let height_left = height_top_left + (height_bottom_left - height_top_left) * grid_offset_z;
+        let height_right =
+            height_top_right + (height_bottom_right - height_top_right) * grid_offset_z;
+
+        height_left + (height_right - height_left) * grid_offset_x
+    }
+
+    let mut x = rng.gen_range(0.0, width as f32);
+    let mut z = rng.gen_range(0.0, depth as f32);
+    let mut dir_x: f32 = 0.0;
+    let mut dir_z: f32 = 0.0;
+    let mut speed: f32 = config.erosion_initial_speed;
+    let mut water: f32 = config.erosion_initial_water_volume;
+    let mut sediment: f32 = 0.0;
+
+    for _ in 0..config.erosion_max_droplet_lifetime {
+        if x < 1.0 || z < 1.0 || x + 1.0 >= width as f32 || z + 1.0 >= depth as f32 {
+            break;
+        }
+
+        let cur_y = get_height_interpolated(x, z, height_map, width);
+
+        if cur_y / config.max_height <= config.erosion_stop_height_end {
+            break;
+        }
+
+        let left = get_height_interpolated(x - 1.0, z, height_map, width);
+        let top = get_height_interpolated(x, z - 1.0, height_map, width);
+        let right = get_height_interpolated(x + 1.0, z, height_map, width);
+        let bottom = get_height_interpolated(x, z + 1.0, height_map, width);
+
+        let mut norm_x = left - right;
+        let mut norm_y: f32 = 2.0;
+        let mut norm_z = top - bottom;
+
+        let len2 = norm_x.powi(2) + norm_y.powi(2) + norm_z.powi(2);
+        let scale = 1.0 / len2.sqrt();
+        norm_x *= scale;
+        norm_y *= scale;
+        norm_z *= scale;
+
+        if norm_y == 1.0 {
+            break;
+        }
+
+        let prev_x = x;
+        let prev_z = z;
+        dir_x = dir_x * config.erosion_inertia + norm_x * (1.0 - config.erosion_inertia);
+        dir_z = dir_z * config.erosion_inertia + norm_z * (1.0 - config.erosion_inertia);
+        if dir_x == 0.0 && dir_z == 0.0 {
+            break;
+        }
+        let len = (dir_x * dir_x + dir_z * dir_z).sqrt();
+        dir_x /= len;
+        dir_z /= len;
+        x += dir_x;
+        z += dir_z;
+        let delta_height = get_height_interpolated(x, z, height_map, width) - cur_y;
+
+        let dist_to_edge = min(
+            prev_x,
+            min(prev_z, min(width as f32 - prev_x, depth as f32 - prev_z)),
+        );
+        if dist_to_edge <= config.erosion_edge_damp_min_distance {
+            break;
+        }
+
+        if let Some(flow) = flow.as_deref_mut() {
+            let floor_x = prev_x.floor() as usize;
+            let floor_z = prev_z.floor() as usize;
+            let grid_offset_x = prev_x - floor_x as f32;
+            let grid_offset_z = prev_z - floor_z as f32;
+
+            flow[floor_z * (width as usize + 1) + floor_x] +=
+                water * (1.0 - grid_offset_x) * (1.0 - grid_offset_z);
+            flow[floor_z * (width as usize + 1) + (floor_x + 1)] +=
+                water * grid_offset_x * (1.0 - grid_offset_z);
+            flow[(floor_z + 1) * (width as usize + 1) + floor_x] +=
+                water * (1.0 - grid_offset_x) * grid_offset_z;
+            flow[(floor_z + 1) * (width as usize + 1) + (floor_x + 1)] +=
+                water * grid_offset_x * grid_offset_z;
+        }
+
+        let mut damp_factor = if dist_to_edge <= config.erosion_edge_damp_max_distance {
+            ((dist_to_edge - config.erosion_edge_damp_min_distance)
+                / (config.erosion_edge_damp_max_distance - config.erosion_edge_damp_min_distance))
+                .powf(config.erosion_edge_damp_strength)
+        } else {
+            1.0
+        };
+
+        // Based off https://github.com/SebLague/Hydraulic-Erosion
+        // MIT License
+        // Copyright (c) 2019 Sebastian Lague
+        // Permission is hereby granted, free of charge, to any person obtaining a copy
+        // of this software and associated documentation files (the "Software"), to deal
+        // in the Software without restriction, including without limitation the rights
+        // to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+        // copies of the Software, and to permit persons to whom the Software is
+        // furnished to do so, subject to the following conditions:
+        // The above copyright notice and this permission notice shall be included in all
+        // copies or substantial portions of the Software.
+        // Calculate the droplet's sediment capacity (higher when moving fast down a slope and contains lots of water)
+        let sediment_capacity = max(
+            -delta_height * speed * water * config.erosion_sediment_capacity_factor,
+            config.erosion_min_sediment_capacity,
+        );
+
+        damp_factor *= min(
+            ((cur_y / config.max_height) - config.erosion_stop_height_end)
+                / (config.erosion_stop_height_start - config.erosion_stop_height_end),
+            1.0,
+        );
+
+        // If carrying more sediment than capacity, or if flowing uphill:
+        if sediment > sediment_capacity || delta_height > 0.0 {
+            // If moving uphill (deltaHeight > 0) try fill up to the current height, otherwise deposit a fraction of the excess sediment
+            let amount_to_deposit = damp_factor
+                * (if delta_height > 0.0 {
+                    min(delta_height, sediment)
+                } else {
+                    (sediment - sediment_capacity) * config.erosion_deposit_speed
+                });
+
+            let floor_x = prev_x.floor() as usize;
+            let floor_z = prev_z.floor() as usize;
+            let grid_offset_x = prev_x - floor_x as f32;
+            let grid_offset_z = prev_z - floor_z as f32;
+
+            height_map[floor_z * (width as usize + 1) + floor_x] +=
+                amount_to_deposit * (1.0 - grid_offset_x) * (1.0 - grid_offset_z);
+            height_map[floor_z * (width as usize + 1) + (floor_x + 1)] +=
+                amount_to_deposit * grid_offset_x * (1.0 - grid_offset_z);
+            height_map[(floor_z + 1) * (width as usize + 1) + floor_x] +=
+                amount_to_deposit * (1.0 - grid_offset_x) * grid_offset_z;
+            height_map[(floor_z + 1) * (width as usize + 1) + (floor_x + 1)] +=
+                amount_to_deposit * grid_offset_x * grid_offset_z;
+
+            sediment -= amount_to_deposit;
+        } else {
+            // Erode a fraction of the droplet's current carry capacity.
+            // Clamp the erosion to the change in height so that it doesn't dig a hole in the terrain behind the droplet
+            let amount_to_erode = damp_factor
+                * min(
+                    (sediment_capacity - sediment) * config.erosion_erode_speed,
+                    -delta_height,
+                );
+            for dz in (-EROSION_KERNEL_RADIUS)..=EROSION_KERNEL_RADIUS {
+                for dx in (-EROSION_KERNEL_RADIUS)..=EROSION_KERNEL_RADIUS {
+                    let weight = EROSION_KERNEL[((dz + EROSION_KERNEL_RADIUS)
+                        * (EROSION_KERNEL_RADIUS * 2 + 1)
+                        + (dx + EROSION_KERNEL_RADIUS))
+                        as usize];
+                    let idx = (prev_z.floor() as isize + dz as isize) as usize
+                        * (width as usize + 1)
+                        + (prev_x.floor() as isize + dx as isize) as usize;
+                    height_map[idx] = max(0.0, height_map[idx] - amount_to_erode * weight);
+                }
+            }
+            sediment += amount_to_erode;
+        }
+
+        speed = max(speed * speed + delta_height * config.erosion_gravity, 0.0).sqrt();
+        water *= 1.0 - config.erosion_evaporate_speed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{erode, ErosionField};
+    use crate::random::Rng;
+    use crate::TerrainConfig;
+
+    #[test]
+    fn droplet_maps_record_the_height_change() {
+        let config = TerrainConfig {
+            chunk_width: 32,
+            chunk_depth: 32,
+            erosion_stop_height_start: 0.02,
+            erosion_stop_height_end: 0.01,
+            ..TerrainConfig::default()
+        };
+        let columns = 33;
+        let original: Vec<f32> = (0..columns * columns)
+            .map(|index| {
+                let (x, z) = ((index % columns) as f32, (index / columns) as f32);
+                100.0 + 2.0 * x + 20.0 * (z * 0.3).sin()
+            })
+            .collect();
+
+        let mut plain = original.clone();
+        let plain_fields = erode(&mut plain, 32, 32, &mut Rng::new(3), &config);
+        assert!(plain_fields.maps().iter().all(Vec::is_empty));
+
+        let mut height_map = original.clone();
+        let config = TerrainConfig {
+            erosion_droplet_maps: true,
+            ..config
+        };
+        let mut fields = erode(&mut height_map, 32, 32, &mut Rng::new(3), &config);
+        assert_eq!(height_map, plain);
+
+        let flow = fields.take(ErosionField::Flow);
+        let deposition = fields.take(ErosionField::Deposition);
+        let erosion_depth = fields.take(ErosionField::ErosionDepth);
+        assert!(flow.iter().all(|&water| water >= 0.0));
+        assert!(flow.iter().any(|&water| water > 0.0));
+        for i in 0..height_map.len() {
+            assert!(deposition[i] >= 0.0 && erosion_depth[i] >= 0.0);
+            let change = deposition[i] - erosion_depth[i];
+            assert!((original[i] + change - height_map[i]).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+mod gltf;
+mod heightmap;
+mod obj;
+mod splatmap;
+mod stl;
+
+pub use gltf::encode_glb;
+pub use heightmap::HeightMap;
+pub use obj::encode_obj;
+pub use splatmap::SplatMap;
+pub use stl::encode_stl;
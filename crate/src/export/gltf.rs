@@ -0,0 +1,228 @@
+use crate::Chunk;
+use serde_json::{json, Value};
+
+const GLB_MAGIC: u32 = 0x4654_6c67;
+const GLB_VERSION: u32 = 2;
+const CHUNK_TYPE_JSON: u32 = 0x4e4f_534a;
+const CHUNK_TYPE_BIN: u32 = 0x004e_4942;
+const COMPONENT_TYPE_UNSIGNED_INT: u32 = 5125;
+const COMPONENT_TYPE_FLOAT: u32 = 5126;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const MODE_TRIANGLES: u32 = 4;
+
+struct GltfBuilder {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl GltfBuilder {
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        let aligned_length = self.buffer.len().div_ceil(4) * 4;
+        self.buffer.resize(aligned_length, 0);
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    fn push_vec3s(&mut self, values: &[f32], with_bounds: bool) -> usize {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let view = self.push_view(&bytes, TARGET_ARRAY_BUFFER);
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": COMPONENT_TYPE_FLOAT,
+            "count": values.len() / 3,
+            "type": "VEC3",
+        });
+        if with_bounds {
+            let mut min = [f32::INFINITY; 3];
+            let mut max = [f32::NEG_INFINITY; 3];
+            for vertex in values.chunks(3) {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(vertex[axis]);
+                    max[axis] = max[axis].max(vertex[axis]);
+                }
+            }
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect();
+        let view = self.push_view(&bytes, TARGET_ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": COMPONENT_TYPE_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+fn push_glb_chunk(glb: &mut Vec<u8>, chunk_type: u32, mut data: Vec<u8>, padding: u8) {
+    let aligned_length = data.len().div_ceil(4) * 4;
+    data.resize(aligned_length, padding);
+    glb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    glb.extend_from_slice(&chunk_type.to_le_bytes());
+    glb.extend_from_slice(&data);
+}
+
+// glTF vertex colors are linear, while the color ramps are sRGB.
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Binary glTF 2.0 holding one mesh and one node per chunk. Vertex positions
+/// are stored relative to the chunk's origin, which becomes the node's
+/// translation, to keep them precise far away from the world origin. glTF
+/// needs at least one mesh, so `chunks` must not be empty.
+pub fn encode_glb(chunks: &[&Chunk]) -> Result<Vec<u8>, String> {
+    if chunks.is_empty() {
+        return Err("a glTF file needs at least one chunk".to_string());
+    }
+    let mut builder = GltfBuilder {
+        buffer: Vec::new(),
+        buffer_views: Vec::new(),
+        accessors: Vec::new(),
+    };
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+
+    for chunk in chunks {
+        let origin_x = (chunk.chunk_x * chunk.width as i32) as f32;
+        let origin_z = (chunk.chunk_z * chunk.depth as i32) as f32;
+        let mut positions = chunk.vertices.clone();
+        for vertex in positions.chunks_mut(3) {
+            vertex[0] -= origin_x;
+            vertex[2] -= origin_z;
+        }
+
+        let position = builder.push_vec3s(&positions, true);
+        let normal = builder.push_vec3s(&chunk.normals, false);
+        let colors: Vec<f32> = chunk.colors.iter().copied().map(srgb_to_linear).collect();
+        let color = builder.push_vec3s(&colors, false);
+        let indices = builder.push_indices(&chunk.indices);
+
+        let name = format!("chunk_{}_{}", chunk.chunk_x, chunk.chunk_z);
+        meshes.push(json!({
+            "name": name,
+            "primitives": [{
+                "attributes": {
+                    "POSITION": position,
+                    "NORMAL": normal,
+                    "COLOR_0": color,
+                },
+                "indices": indices,
+                "mode": MODE_TRIANGLES,
+            }],
+        }));
+        nodes.push(json!({
+            "name": name,
+            "mesh": meshes.len() - 1,
+            "translation": [origin_x, 0.0, origin_z],
+        }));
+    }
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "Terrain" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<usize>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "accessors": builder.accessors,
+        "bufferViews": builder.buffer_views,
+        "buffers": [{ "byteLength": builder.buffer.len() }],
+    });
+
+    let mut glb = Vec::new();
+    glb.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+    glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+    glb.extend_from_slice(&0u32.to_le_bytes());
+    push_glb_chunk(
+        &mut glb,
+        CHUNK_TYPE_JSON,
+        document.to_string().into_bytes(),
+        b' ',
+    );
+    push_glb_chunk(&mut glb, CHUNK_TYPE_BIN, builder.buffer, 0);
+    let length = glb.len() as u32;
+    glb[8..12].copy_from_slice(&length.to_le_bytes());
+    Ok(glb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_glb;
+    use crate::{TerrainConfig, TerrainGenerator};
+    use serde_json::Value;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    #[test]
+    fn encodes_one_node_per_chunk() {
+        let config = TerrainConfig {
+            chunk_width: 8,
+            chunk_depth: 8,
+            ..TerrainConfig::default()
+        };
+        let generator = TerrainGenerator::new(1, config).unwrap();
+        let first = generator.generate_chunk(0, 0);
+        let second = generator.generate_chunk(1, 0);
+        let glb = encode_glb(&[&first, &second]).unwrap();
+        assert!(encode_glb(&[]).is_err());
+
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(read_u32(&glb, 8) as usize, glb.len());
+        let json_length = read_u32(&glb, 12) as usize;
+        assert_eq!(json_length % 4, 0);
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        let bin_length = read_u32(&glb, 20 + json_length) as usize;
+        assert_eq!(
+            document["buffers"][0]["byteLength"].as_u64().unwrap() as usize,
+            bin_length
+        );
+
+        assert_eq!(document["nodes"].as_array().unwrap().len(), 2);
+        assert_eq!(document["nodes"][1]["translation"][0], 8.0);
+        let position = &document["accessors"][4];
+        assert_eq!(position["count"], 81);
+        assert_eq!(position["min"][0], 0.0);
+        assert_eq!(position["max"][0], 8.0);
+        let indices = &document["accessors"][3];
+        assert_eq!(indices["count"], 8 * 8 * 6);
+
+        // Colors are stored linear.
+        let color_view = document["accessors"][2]["bufferView"].as_u64().unwrap() as usize;
+        let offset = document["bufferViews"][color_view]["byteOffset"]
+            .as_u64()
+            .unwrap() as usize;
+        let bin_start = 20 + json_length + 8;
+        let red = f32::from_bits(read_u32(&glb, bin_start + offset));
+        assert!((red - first.colors[0].powf(2.2)).abs() < 0.01);
+        assert!(red < first.colors[0] || first.colors[0] == 0.0);
+    }
+}
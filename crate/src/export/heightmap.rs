@@ -0,0 +1,167 @@
+use crate::Chunk;
+use png::{BitDepth, ColorType, Encoder};
+
+/// Heights of one chunk, or of several chunks stitched into one grid, laid
+/// out row by row along z like `Chunk::height_map`.
+pub struct HeightMap {
+    pub width: u32,
+    pub depth: u32,
+    pub heights: Vec<f32>,
+}
+
+impl HeightMap {
+    pub fn from_chunk(chunk: &Chunk) -> HeightMap {
+        HeightMap {
+            width: chunk.width + 1,
+            depth: chunk.depth + 1,
+            heights: chunk.height_map.clone(),
+        }
+    }
+
+    /// An empty grid for `chunks_x` by `chunks_z` chunks, to be filled with
+    /// `insert_chunk`. Neighbouring chunks share their edge samples.
+    pub fn for_region(
+        chunks_x: u32,
+        chunks_z: u32,
+        chunk_width: u32,
+        chunk_depth: u32,
+    ) -> HeightMap {
+        let width = chunks_x * chunk_width + 1;
+        let depth = chunks_z * chunk_depth + 1;
+        HeightMap {
+            width,
+            depth,
+            heights: vec![0.0; (width * depth) as usize],
+        }
+    }
+
+    /// Copies `chunk` into the grid, `column` and `row` being the chunk's
+    /// position counted in chunks from the start of the region.
+    pub fn insert_chunk(&mut self, column: u32, row: u32, chunk: &Chunk) {
+        let offset_x = column * chunk.width;
+        let offset_z = row * chunk.depth;
+        assert!(offset_x + chunk.width < self.width && offset_z + chunk.depth < self.depth);
+        let chunk_row = (chunk.width + 1) as usize;
+        for z in 0..=chunk.depth as usize {
+            let start = (offset_z as usize + z) * self.width as usize + offset_x as usize;
+            self.heights[start..start + chunk_row]
+                .copy_from_slice(&chunk.height_map[z * chunk_row..(z + 1) * chunk_row]);
+        }
+    }
+
+    /// Heights scaled so that 0 maps to 0 and `max_height` to 65535.
+    pub fn to_u16(&self, max_height: f32) -> Vec<u16> {
+        self.heights
+            .iter()
+            .map(|height| ((height / max_height).clamp(0.0, 1.0) * 65535.0).round() as u16)
+            .collect()
+    }
+
+    /// Little-endian 16 bit RAW, as read by the Unity and Unreal terrain
+    /// importers. The file carries no header, so `max_height` must be passed
+    /// to the importer as the terrain height.
+    pub fn encode_r16(&self, max_height: f32) -> Vec<u8> {
+        self.to_u16(max_height)
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect()
+    }
+
+    /// 16 bit grayscale PNG. The vertical scale is recorded in `tEXt` chunks:
+    /// `max_height` is the height of a full white sample.
+    pub fn encode_png(&self, max_height: f32) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut bytes, self.width, self.depth);
+            encoder.set_color(ColorType::Grayscale);
+            encoder.set_depth(BitDepth::Sixteen);
+            encoder
+                .add_text_chunk("max_height".to_string(), max_height.to_string())
+                .map_err(|error| error.to_string())?;
+            encoder
+                .add_text_chunk("Software".to_string(), "Terrain".to_string())
+                .map_err(|error| error.to_string())?;
+            let mut writer = encoder.write_header().map_err(|error| error.to_string())?;
+            let data: Vec<u8> = self
+                .to_u16(max_height)
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect();
+            writer
+                .write_image_data(&data)
+                .map_err(|error| error.to_string())?;
+            writer.finish().map_err(|error| error.to_string())?;
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeightMap;
+    use crate::Chunk;
+
+    fn chunk(heights: Vec<f32>) -> Chunk {
+        Chunk {
+            chunk_x: 0,
+            chunk_z: 0,
+            width: 2,
+            depth: 1,
+            height_map: heights,
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            indices: Vec::new(),
+            splat_weights: Vec::new(),
+            splat_layer_count: 0,
+            water_map: Vec::new(),
+            sediment_map: Vec::new(),
+            flow_map: Vec::new(),
+            deposition_map: Vec::new(),
+            erosion_depth_map: Vec::new(),
+            flow_accumulation_map: Vec::new(),
+            rivers: Vec::new(),
+            lake_level_map: Vec::new(),
+            lakes: Vec::new(),
+            temperature_map: Vec::new(),
+            moisture_map: Vec::new(),
+            biome_map: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn stitches_chunks_sharing_edges() {
+        let mut height_map = HeightMap::for_region(2, 1, 2, 1);
+        height_map.insert_chunk(0, 0, &chunk(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]));
+        height_map.insert_chunk(1, 0, &chunk(vec![2.0, 6.0, 7.0, 5.0, 8.0, 9.0]));
+        assert_eq!((height_map.width, height_map.depth), (5, 2));
+        assert_eq!(
+            height_map.heights,
+            vec![0.0, 1.0, 2.0, 6.0, 7.0, 3.0, 4.0, 5.0, 8.0, 9.0]
+        );
+    }
+
+    #[test]
+    fn encodes_r16_and_png() {
+        let height_map = HeightMap::from_chunk(&chunk(vec![0.0, 256.0, 512.0, -1.0, 600.0, 128.0]));
+        assert_eq!(
+            height_map.to_u16(512.0),
+            vec![0, 32768, 65535, 0, 65535, 16384]
+        );
+        assert_eq!(&height_map.encode_r16(512.0)[..4], &[0, 0, 0, 128]);
+
+        let png = height_map.encode_png(512.0).unwrap();
+        let decoder = png::Decoder::new(&png[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+        assert!(info
+            .uncompressed_latin1_text
+            .iter()
+            .any(|chunk| chunk.keyword == "max_height" && chunk.text == "512"));
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        assert_eq!(&data[..6], &[0, 0, 128, 0, 255, 255]);
+    }
+}
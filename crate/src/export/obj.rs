@@ -0,0 +1,64 @@
+use crate::Chunk;
+
+/// Wavefront OBJ with one object per chunk, in world coordinates. Vertex
+/// colors follow the widely supported `v x y z r g b` extension and every
+/// face references per-vertex normals.
+pub fn encode_obj(chunks: &[&Chunk]) -> String {
+    let mut obj = String::from("# Terrain\n");
+    let mut first_vertex = 1;
+    for chunk in chunks {
+        obj.push_str(&format!("o chunk_{}_{}\n", chunk.chunk_x, chunk.chunk_z));
+        for (vertex, color) in chunk.vertices.chunks(3).zip(chunk.colors.chunks(3)) {
+            obj.push_str(&format!(
+                "v {} {} {} {} {} {}\n",
+                vertex[0], vertex[1], vertex[2], color[0], color[1], color[2]
+            ));
+        }
+        for normal in chunk.normals.chunks(3) {
+            obj.push_str(&format!("vn {} {} {}\n", normal[0], normal[1], normal[2]));
+        }
+        for face in chunk.indices.chunks(3) {
+            let (a, b, c) = (
+                face[0] + first_vertex,
+                face[1] + first_vertex,
+                face[2] + first_vertex,
+            );
+            obj.push_str(&format!("f {}//{} {}//{} {}//{}\n", a, a, b, b, c, c));
+        }
+        first_vertex += (chunk.vertices.len() / 3) as u32;
+    }
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_obj;
+    use crate::{TerrainConfig, TerrainGenerator};
+
+    #[test]
+    fn offsets_indices_of_later_chunks() {
+        let config = TerrainConfig {
+            chunk_width: 8,
+            chunk_depth: 8,
+            ..TerrainConfig::default()
+        };
+        let generator = TerrainGenerator::new(1, config).unwrap();
+        let first = generator.generate_chunk(0, 0);
+        let second = generator.generate_chunk(0, 1);
+        let obj = encode_obj(&[&first, &second]);
+
+        let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        assert_eq!(count("o "), 2);
+        assert_eq!(count("v "), 2 * 81);
+        assert_eq!(count("vn "), 2 * 81);
+        assert_eq!(count("f "), 2 * 8 * 8 * 2);
+        assert!(obj.lines().any(|line| line == "f 82//82 91//91 83//83"));
+        let vertex: Vec<&str> = obj
+            .lines()
+            .find(|line| line.starts_with("v "))
+            .unwrap()
+            .split(' ')
+            .collect();
+        assert_eq!(vertex.len(), 7);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::splat::pack_splat_weights;
+use crate::Chunk;
+use png::{BitDepth, ColorType, Encoder};
+
+/// Splat weights of one chunk, or of several chunks stitched into one grid,
+/// laid out like `HeightMap` with `layer_count` weights per sample.
+pub struct SplatMap {
+    pub width: u32,
+    pub depth: u32,
+    pub layer_count: u32,
+    pub weights: Vec<f32>,
+}
+
+impl SplatMap {
+    pub fn from_chunk(chunk: &Chunk) -> SplatMap {
+        SplatMap {
+            width: chunk.width + 1,
+            depth: chunk.depth + 1,
+            layer_count: chunk.splat_layer_count,
+            weights: chunk.splat_weights.clone(),
+        }
+    }
+
+    /// An empty grid for `chunks_x` by `chunks_z` chunks, to be filled with
+    /// `insert_chunk`.
+    pub fn for_region(
+        chunks_x: u32,
+        chunks_z: u32,
+        chunk_width: u32,
+        chunk_depth: u32,
+        layer_count: u32,
+    ) -> SplatMap {
+        let width = chunks_x * chunk_width + 1;
+        let depth = chunks_z * chunk_depth + 1;
+        SplatMap {
+            width,
+            depth,
+            layer_count,
+            weights: vec![0.0; (width * depth * layer_count) as usize],
+        }
+    }
+
+    /// Copies `chunk` into the grid, like `HeightMap::insert_chunk`.
+    pub fn insert_chunk(&mut self, column: u32, row: u32, chunk: &Chunk) {
+        assert_eq!(chunk.splat_layer_count, self.layer_count);
+        let offset_x = column * chunk.width;
+        let offset_z = row * chunk.depth;
+        assert!(offset_x + chunk.width < self.width && offset_z + chunk.depth < self.depth);
+        let layer_count = self.layer_count as usize;
+        let chunk_row = (chunk.width + 1) as usize * layer_count;
+        for z in 0..=chunk.depth as usize {
+            let start =
+                ((offset_z as usize + z) * self.width as usize + offset_x as usize) * layer_count;
+            self.weights[start..start + chunk_row]
+                .copy_from_slice(&chunk.splat_weights[z * chunk_row..(z + 1) * chunk_row]);
+        }
+    }
+
+    /// Number of RGBA images needed to hold every layer.
+    pub fn page_count(&self) -> u32 {
+        self.layer_count.div_ceil(4)
+    }
+
+    /// 8 bit RGBA PNG holding layers `4 * page` to `4 * page + 3`, one per
+    /// channel. Channels past the last layer are zero.
+    pub fn encode_png(&self, page: u32) -> Result<Vec<u8>, String> {
+        if page >= self.page_count() {
+            return Err(format!(
+                "splat map page {} out of range, there are {}",
+                page,
+                self.page_count()
+            ));
+        }
+        let packed = pack_splat_weights(&self.weights, self.layer_count as usize);
+        let stride = self.page_count() as usize * 4;
+        let data: Vec<u8> = packed
+            .chunks(stride)
+            .flat_map(|texels| texels[page as usize * 4..page as usize * 4 + 4].to_vec())
+            .collect();
+
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut bytes, self.width, self.depth);
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_depth(BitDepth::Eight);
+            encoder
+                .add_text_chunk("Software".to_string(), "Terrain".to_string())
+                .map_err(|error| error.to_string())?;
+            let mut writer = encoder.write_header().map_err(|error| error.to_string())?;
+            writer
+                .write_image_data(&data)
+                .map_err(|error| error.to_string())?;
+            writer.finish().map_err(|error| error.to_string())?;
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SplatMap;
+
+    #[test]
+    fn encodes_one_page_per_four_layers() {
+        let splat_map = SplatMap {
+            width: 2,
+            depth: 1,
+            layer_count: 5,
+            weights: vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.5, 0.5, 0.0, 0.0, 0.0],
+        };
+        assert_eq!(splat_map.page_count(), 2);
+        assert!(splat_map.encode_png(2).is_err());
+
+        let png = splat_map.encode_png(1).unwrap();
+        let decoder = png::Decoder::new(&png[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        assert_eq!(data, vec![255, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::HeightMap;
+
+const HEADER: &[u8] = b"Terrain binary STL";
+
+type Point = [f32; 3];
+
+// Triangles are collected with y up, as everywhere else in the crate, and
+// rotated to z up when written since that is what slicers expect.
+fn to_z_up(point: Point) -> Point {
+    [point[0], -point[2], point[1]]
+}
+
+fn facet_normal(triangle: &[Point; 3]) -> Point {
+    let [a, b, c] = triangle;
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let normal = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let length = (normal[0].powi(2) + normal[1].powi(2) + normal[2].powi(2)).sqrt();
+    if length > 0.0 {
+        [normal[0] / length, normal[1] / length, normal[2] / length]
+    } else {
+        [0.0; 3]
+    }
+}
+
+/// Binary STL of the height map's surface, one unit per cell with z up.
+/// With `base_depth`, the surface is closed into a watertight solid by side
+/// walls and a flat base `base_depth` below the lowest sample, which must be
+/// positive and finite.
+pub fn encode_stl(height_map: &HeightMap, base_depth: Option<f32>) -> Result<Vec<u8>, String> {
+    let width = height_map.width as usize;
+    let depth = height_map.depth as usize;
+    if width < 2 || depth < 2 {
+        return Err("an STL file needs a height map at least 2 samples wide and deep".to_string());
+    }
+    if let Some(base_depth) = base_depth {
+        if !base_depth.is_finite() || base_depth <= 0.0 {
+            return Err(format!(
+                "the STL base depth must be positive and finite, not {}",
+                base_depth
+            ));
+        }
+    }
+    let point = |x: usize, z: usize| [x as f32, height_map.heights[z * width + x], z as f32];
+
+    let mut triangles = Vec::new();
+    for z in 0..depth - 1 {
+        for x in 0..width - 1 {
+            // Same split and winding as `build_indices`.
+            triangles.push([point(x, z), point(x, z + 1), point(x + 1, z)]);
+            triangles.push([point(x, z + 1), point(x + 1, z + 1), point(x + 1, z)]);
+        }
+    }
+
+    if let Some(base_depth) = base_depth {
+        let lowest = height_map
+            .heights
+            .iter()
+            .fold(f32::INFINITY, |lowest, &height| lowest.min(height));
+        let base = lowest - base_depth;
+
+        // The border, counter-clockwise when seen from above.
+        let mut border = Vec::with_capacity(2 * (width + depth));
+        border.extend((0..depth - 1).map(|z| (0, z)));
+        border.extend((0..width - 1).map(|x| (x, depth - 1)));
+        border.extend((1..depth).rev().map(|z| (width - 1, z)));
+        border.extend((1..width).rev().map(|x| (x, 0)));
+
+        let center = [(width - 1) as f32 / 2.0, base, (depth - 1) as f32 / 2.0];
+        for (i, &(x, z)) in border.iter().enumerate() {
+            let (next_x, next_z) = border[(i + 1) % border.len()];
+            let top = point(x, z);
+            let next_top = point(next_x, next_z);
+            let bottom = [top[0], base, top[2]];
+            let next_bottom = [next_top[0], base, next_top[2]];
+            triangles.push([top, bottom, next_top]);
+            triangles.push([next_top, bottom, next_bottom]);
+            triangles.push([center, next_bottom, bottom]);
+        }
+    }
+
+    let mut stl = Vec::with_capacity(84 + triangles.len() * 50);
+    stl.extend_from_slice(HEADER);
+    stl.resize(80, 0);
+    stl.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for triangle in triangles.iter() {
+        let triangle = [
+            to_z_up(triangle[0]),
+            to_z_up(triangle[1]),
+            to_z_up(triangle[2]),
+        ];
+        let normal = facet_normal(&triangle);
+        for value in normal.iter().chain(triangle.iter().flatten()) {
+            stl.extend_from_slice(&value.to_le_bytes());
+        }
+        stl.extend_from_slice(&0u16.to_le_bytes());
+    }
+    Ok(stl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_stl;
+    use crate::HeightMap;
+    use std::collections::HashMap;
+
+    fn height_map() -> HeightMap {
+        HeightMap {
+            width: 4,
+            depth: 3,
+            heights: vec![
+                1.0, 2.0, 3.0, 2.0, //
+                0.5, 4.0, 1.0, 2.0, //
+                1.0, 1.5, 2.5, 3.0,
+            ],
+        }
+    }
+
+    fn triangles(stl: &[u8]) -> Vec<[[u32; 3]; 3]> {
+        let count = u32::from_le_bytes([stl[80], stl[81], stl[82], stl[83]]) as usize;
+        assert_eq!(stl.len(), 84 + count * 50);
+        (0..count)
+            .map(|i| {
+                let value = |j: usize| {
+                    let offset = 84 + i * 50 + 12 + j * 4;
+                    u32::from_le_bytes([
+                        stl[offset],
+                        stl[offset + 1],
+                        stl[offset + 2],
+                        stl[offset + 3],
+                    ])
+                };
+                let vertex = |k: usize| [value(k * 3), value(k * 3 + 1), value(k * 3 + 2)];
+                [vertex(0), vertex(1), vertex(2)]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodes_surface() {
+        let stl = encode_stl(&height_map(), None).unwrap();
+        assert!(!stl.starts_with(b"solid"));
+        assert_eq!(triangles(&stl).len(), 3 * 2 * 2);
+        let normal_z = f32::from_le_bytes([stl[92], stl[93], stl[94], stl[95]]);
+        assert!(normal_z > 0.0, "the surface should face up");
+    }
+
+    #[test]
+    fn encodes_watertight_solid() {
+        let stl = encode_stl(&height_map(), Some(2.0)).unwrap();
+        let triangles = triangles(&stl);
+        assert_eq!(triangles.len(), 3 * 2 * 2 + 3 * 10);
+
+        // Closed and consistently oriented: every directed edge is matched by
+        // exactly one edge running the other way.
+        let mut edges = HashMap::new();
+        for triangle in triangles.iter() {
+            for k in 0..3 {
+                *edges
+                    .entry((triangle[k], triangle[(k + 1) % 3]))
+                    .or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in edges.iter() {
+            assert_eq!(count, 1);
+            assert_eq!(edges.get(&(b, a)), Some(&1));
+        }
+    }
+
+    #[test]
+    fn rejects_empty_height_maps_and_bases_that_are_not_below_the_surface() {
+        for &(width, depth) in [(0, 3), (4, 0), (1, 3)].iter() {
+            let height_map = HeightMap {
+                width,
+                depth,
+                heights: vec![0.0; (width * depth) as usize],
+            };
+            assert!(encode_stl(&height_map, None).is_err());
+        }
+        for &base_depth in [0.0, -2.0, f32::NAN, f32::INFINITY].iter() {
+            assert!(encode_stl(&height_map(), Some(base_depth)).is_err());
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use crate::apron::{crop_ring, edge_weight, generate_apron_height_map};
+use crate::biome::{build_biome_colors, BiomeTable, BiomeWeightGrid};
+use crate::climate::{moisture_map, temperature_map};
+use crate::color::{build_colors, ColorRamp};
+use crate::erosion::{erode, ErosionField};
+use crate::height::HeightSampler;
+use crate::height_graph::HeightGraph;
+use crate::lakes::{generate_lakes, Lake, LakeMode};
+use crate::mesh::{
+    build_adaptive_mesh, build_indices, build_lod_mesh, build_surface, check_lod_level,
+    check_max_error, ChunkMesh,
+};
+use crate::random::{region_seed, Rng};
+use crate::rivers::{carve_rivers, extract_rivers, River};
+use crate::splat::{build_splat_weights, SplatLayers};
+use crate::TerrainConfig;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+type BorderHeight<'a> = Box<dyn Fn(i32, i32) -> f32 + 'a>;
+
+/// The generated buffers of a single chunk. Heights are laid out row by row
+/// (`z * (width + 1) + x`), and vertices, normals and colors hold three
+/// floats per height map entry. `splat_weights` holds `splat_layer_count`
+/// weights per height map entry, and is empty unless the generator has splat
+/// layers. `water_map` and `sediment_map` hold the water depth and suspended
+/// sediment left by pipe erosion, and are empty unless it is enabled.
+/// `flow_map`, `deposition_map` and `erosion_depth_map` hold the water volume
+/// of the droplets that passed each entry, and how much material they
+/// deposited or carved away there in world units. They are empty unless
+/// `erosion_droplet_maps` is set. With a `river_catchment_threshold`,
+/// `flow_accumulation_map` holds the number of cells draining through each
+/// entry and `rivers` the river network traced on the finished heights,
+/// including the stretches reaching past the chunk; both are empty otherwise.
+/// With seamless erosion, lakes filling and rivers carving the terrain fade
+/// out towards the chunk edges, which keep the heights the neighbouring
+/// chunks see. With `lake_mode` set to `Lakes`, `lake_level_map`
+/// holds the water level of every lake entry (0 elsewhere) and `lakes` the
+/// lakes themselves; both are empty otherwise. With a biome table,
+/// `temperature_map` and `moisture_map` hold the climate of every entry in
+/// [0, 1] and `biome_map` the index of its biome in the table, and the
+/// colors come from the biomes' color ramps; all three are empty otherwise.
+pub struct Chunk {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub width: u32,
+    pub depth: u32,
+    pub height_map: Vec<f32>,
+    pub vertices: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub colors: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub splat_weights: Vec<f32>,
+    pub splat_layer_count: u32,
+    pub water_map: Vec<f32>,
+    pub sediment_map: Vec<f32>,
+    pub flow_map: Vec<f32>,
+    pub deposition_map: Vec<f32>,
+    pub erosion_depth_map: Vec<f32>,
+    pub flow_accumulation_map: Vec<f32>,
+    pub rivers: Vec<River>,
+    pub lake_level_map: Vec<f32>,
+    pub lakes: Vec<Lake>,
+    pub temperature_map: Vec<f32>,
+    pub moisture_map: Vec<f32>,
+    pub biome_map: Vec<u8>,
+}
+
+impl Chunk {
+    /// The chunk's mesh at a level of detail, keeping every height map entry
+    /// at level 0, every other one at level 1 and so on, row by row. The
+    /// highest level keeps the corners of the square tiles as long as the
+    /// chunk's shorter side.
+    pub fn lod_mesh(&self, level: u32) -> Result<ChunkMesh, String> {
+        build_lod_mesh(self, level)
+    }
+
+    /// The chunk's mesh with as few triangles as keep the surface within
+    /// `max_error` of every height map entry, splitting triangles only where
+    /// the terrain needs it.
+    pub fn adaptive_mesh(&self, max_error: f32) -> Result<ChunkMesh, String> {
+        build_adaptive_mesh(self, max_error)
+    }
+}
+
+/// Owns everything needed to generate a world, so several worlds can be
+/// generated side by side in the same module.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct TerrainGenerator {
+    seed: u32,
+    config: TerrainConfig,
+    color_ramp: ColorRamp,
+    splat_layers: Option<SplatLayers>,
+    height_graph: Option<HeightGraph>,
+    biome_table: Option<BiomeTable>,
+}
+
+const CHUNK_REGION_KIND: u32 = 0;
+
+// Applies the heights lakes and rivers left to the chunk's heights and
+// erosion. With seamless erosion the changes fade out towards the chunk edges,
+// which the neighbouring chunks share and might have changed differently.
+fn apply_changes(
+    height_map: &mut [f32],
+    erosion: &mut [f32],
+    changed: &[f32],
+    config: &TerrainConfig,
+) {
+    let row = config.chunk_width as usize + 1;
+    for (index, (height, &changed)) in height_map.iter_mut().zip(changed).enumerate() {
+        let weight = if config.erosion_apron > 0 {
+            let (x, z) = ((index % row) as u32, (index / row) as u32);
+            edge_weight(
+                x,
+                z,
+                config.chunk_width,
+                config.chunk_depth,
+                config.erosion_apron,
+            )
+        } else {
+            1.0
+        };
+        let new_height = if weight >= 1.0 {
+            changed
+        } else {
+            *height + weight * (changed - *height)
+        };
+        erosion[index] += new_height - *height;
+        *height = new_height;
+    }
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u32, config: TerrainConfig) -> Result<TerrainGenerator, String> {
+        config.validate()?;
+        Ok(TerrainGenerator {
+            seed,
+            config,
+            color_ramp: ColorRamp::default(),
+            splat_layers: None,
+            height_graph: None,
+            biome_table: None,
+        })
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
+    pub fn config(&self) -> TerrainConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: TerrainConfig) -> Result<(), String> {
+        config.validate()?;
+        self.config = config;
+        Ok(())
+    }
+
+    pub fn color_ramp(&self) -> &ColorRamp {
+        &self.color_ramp
+    }
+
+    pub fn set_color_ramp(&mut self, color_ramp: ColorRamp) {
+        self.color_ramp = color_ramp;
+    }
+
+    pub fn splat_layers(&self) -> Option<&SplatLayers> {
+        self.splat_layers.as_ref()
+    }
+
+    pub fn set_splat_layers(&mut self, splat_layers: Option<SplatLayers>) {
+        self.splat_layers = splat_layers;
+    }
+
+    pub fn height_graph(&self) -> Option<&HeightGraph> {
+        self.height_graph.as_ref()
+    }
+
+    pub fn set_height_graph(&mut self, height_graph: Option<HeightGraph>) {
+        self.height_graph = height_graph;
+    }
+
+    pub fn biome_table(&self) -> Option<&BiomeTable> {
+        self.biome_table.as_ref()
+    }
+
+    pub fn set_biome_table(&mut self, biome_table: Option<BiomeTable>) {
+        self.biome_table = biome_table;
+    }
+
+    pub fn generate_chunk(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let seed = self.seed;
+        let config = &self.config;
+        let width = config.chunk_width;
+        let depth = config.chunk_depth;
+
+        let mut sampler = HeightSampler::new(seed, config, self.height_graph.as_ref());
+        // Biome height profiles follow the climate of the terrain without them.
+        let profiled_table = self.biome_table.as_ref().filter(|table| {
+            table
+                .biomes()
+                .iter()
+                .any(|biome| biome.height_profile.is_some())
+        });
+        if let Some(table) = profiled_table {
+            let grid = BiomeWeightGrid::new(
+                table,
+                &|x, z| sampler.calculate_base_height(x, z),
+                chunk_x,
+                chunk_z,
+                config,
+            );
+            sampler = sampler.with_biomes(seed, table, grid);
+        }
+
+        // With seamless erosion, depressions and rivers are found on the whole
+        // apron so both chunks along an edge see the same ones.
+        let has_rivers = config.river_catchment_threshold > 0.0;
+        let ring = if config.lake_mode != LakeMode::Off || has_rivers {
+            config.erosion_apron
+        } else {
+            0
+        };
+        let padded_height_map = (config.erosion_apron > 0)
+            .then(|| generate_apron_height_map(chunk_x, chunk_z, ring, seed, &sampler, config));
+
+        // Heights just outside the chunk come from the padded height map with
+        // seamless erosion, and from the uneroded noise otherwise.
+        let (mut height_map, mut erosion, mut fields, border_height): (_, _, _, BorderHeight) =
+            if let Some(padded_height_map) = &padded_height_map {
+                (
+                    padded_height_map.to_height_map(),
+                    padded_height_map.to_erosion_map(),
+                    padded_height_map.to_erosion_fields(),
+                    Box::new(move |x, z| padded_height_map.get(x, z)),
+                )
+            } else {
+                let mut rng = Rng::new(region_seed(seed, CHUNK_REGION_KIND, chunk_x, chunk_z));
+
+                let mut height_map = vec![0.0; config.height_map_array_length()];
+                for i in 0..=width {
+                    for j in 0..=depth {
+                        let x = chunk_x * width as i32 + i as i32;
+                        let z = chunk_z * depth as i32 + j as i32;
+                        height_map[(j * (width + 1) + i) as usize] =
+                            sampler.calculate_noise_height(x, z)
+                    }
+                }
+
+                let mut erosion = height_map.clone();
+                let fields = erode(&mut height_map, width, depth, &mut rng, config);
+                for (delta, height) in erosion.iter_mut().zip(height_map.iter()) {
+                    *delta = height - *delta;
+                }
+
+                (
+                    height_map,
+                    erosion,
+                    fields,
+                    Box::new(|x, z| sampler.calculate_noise_height(x, z)),
+                )
+            };
+
+        // Lakes and rivers change the chunk's heights through `apply_changes`.
+        let mut ringed_height_map = match &padded_height_map {
+            Some(padded_height_map) => padded_height_map.to_padded_height_map(ring),
+            None => height_map.clone(),
+        };
+        let (lakes, lake_level_map) = if config.lake_mode != LakeMode::Off {
+            let (lakes, lake_level_map) = generate_lakes(
+                &mut ringed_height_map,
+                width,
+                depth,
+                chunk_x,
+                chunk_z,
+                ring,
+                config,
+            );
+            let lake_level_map = if lake_level_map.is_empty() {
+                lake_level_map
+            } else {
+                let mut lake_level_map = crop_ring(&lake_level_map, width, depth, ring);
+                if let Some(padded_height_map) = &padded_height_map {
+                    padded_height_map.set_edge_lake_levels(&mut lake_level_map);
+                }
+                lake_level_map
+            };
+            (lakes, lake_level_map)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        let (rivers, flow_accumulation_map) = if has_rivers {
+            let (rivers, accumulation) = extract_rivers(
+                &ringed_height_map,
+                width,
+                depth,
+                chunk_x,
+                chunk_z,
+                ring,
+                config,
+            );
+            if config.river_carve {
+                carve_rivers(
+                    &mut ringed_height_map,
+                    width,
+                    depth,
+                    chunk_x,
+                    chunk_z,
+                    ring,
+                    &rivers,
+                    config,
+                );
+            }
+            (rivers, crop_ring(&accumulation, width, depth, ring))
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        if config.lake_mode != LakeMode::Off || (has_rivers && config.river_carve) {
+            apply_changes(
+                &mut height_map,
+                &mut erosion,
+                &crop_ring(&ringed_height_map, width, depth, ring),
+                config,
+            );
+        }
+
+        let (vertices, normals, curvatures) =
+            build_surface(&height_map, width, depth, chunk_x, chunk_z, &*border_height);
+        let indices = build_indices(width, depth);
+        let (temperature_map, moisture_map, biome_map, colors) = match &self.biome_table {
+            Some(table) => {
+                let temperature_map = temperature_map(&height_map, width, depth, chunk_z, config);
+                let moisture_map = moisture_map(
+                    width,
+                    depth,
+                    chunk_x,
+                    chunk_z,
+                    &|x, z| sampler.calculate_noise_height(x, z),
+                    config,
+                );
+                let (biome_map, colors) = build_biome_colors(
+                    &height_map,
+                    &normals,
+                    &curvatures,
+                    &temperature_map,
+                    &moisture_map,
+                    table,
+                    config,
+                );
+                (temperature_map, moisture_map, biome_map, colors)
+            }
+            None => (
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                build_colors(&height_map, &normals, &curvatures, &self.color_ramp, config),
+            ),
+        };
+
+        let (splat_weights, splat_layer_count) = match &self.splat_layers {
+            Some(layers) => (
+                build_splat_weights(&height_map, &normals, &curvatures, &erosion, layers, config),
+                layers.len() as u32,
+            ),
+            None => (Vec::new(), 0),
+        };
+
+        Chunk {
+            chunk_x,
+            chunk_z,
+            width,
+            depth,
+            height_map,
+            vertices,
+            normals,
+            colors,
+            indices,
+            splat_weights,
+            splat_layer_count,
+            water_map: fields.take(ErosionField::Water),
+            sediment_map: fields.take(ErosionField::Sediment),
+            flow_map: fields.take(ErosionField::Flow),
+            deposition_map: fields.take(ErosionField::Deposition),
+            erosion_depth_map: fields.take(ErosionField::ErosionDepth),
+            flow_accumulation_map,
+            rivers,
+            lake_level_map,
+            lakes,
+            temperature_map,
+            moisture_map,
+            biome_map,
+        }
+    }
+
+    /// Generates a chunk and keeps only its mesh at the given level of
+    /// detail, see `Chunk::lod_mesh`. Every call generates and erodes the
+    /// whole chunk again, so to build several meshes of one chunk call
+    /// `Chunk::lod_mesh` on a chunk from `generate_chunk` instead.
+    pub fn generate_chunk_mesh(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        level: u32,
+    ) -> Result<ChunkMesh, String> {
+        check_lod_level(self.config.chunk_width, self.config.chunk_depth, level)?;
+        self.generate_chunk(chunk_x, chunk_z).lod_mesh(level)
+    }
+
+    /// Generates a chunk and keeps only its adaptive mesh, see
+    /// `Chunk::adaptive_mesh`. Like `generate_chunk_mesh` this generates and
+    /// erodes the whole chunk on every call; reuse a generated chunk where
+    /// possible.
+    pub fn generate_chunk_adaptive_mesh(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        max_error: f32,
+    ) -> Result<ChunkMesh, String> {
+        check_max_error(max_error)?;
+        self.generate_chunk(chunk_x, chunk_z)
+            .adaptive_mesh(max_error)
+    }
+}
@@ -0,0 +1,419 @@
+use crate::biome::{BiomeTable, BiomeWeightGrid};
+use crate::height_graph::{CompiledHeightGraph, HeightGraph};
+use crate::TerrainConfig;
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, Seedable, SuperSimplex, Worley};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// The noise summed by the octave loop. `SuperSimplex`, `Perlin`,
+/// `OpenSimplex` and `Worley` (distance to the nearest cell point) are
+/// summed as they are. `Ridged` folds SuperSimplex into sharp crests and
+/// weights every octave by the one before it, so detail gathers on the
+/// ridges; `Billow` folds it into rounded hills with creased valleys.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseFunction {
+    #[default]
+    SuperSimplex,
+    Perlin,
+    OpenSimplex,
+    Worley,
+    Ridged,
+    Billow,
+}
+
+// How strongly an octave of ridged noise lets through the detail of the next.
+const RIDGE_GAIN: f32 = 2.0;
+
+// Each warp level displaces the sample point by two octaves-summed noise
+// fields, one per axis, sampled at the point displaced by the level before
+// it. This is the nested fBm warp described by Inigo Quilez in "Domain
+// Warping", with every field seeded separately.
+const WARP_OCTAVES: u32 = 3;
+
+// Continentalness sums a few octaves of plain noise. The coastline is the
+// value that leaves `continent_land_ratio` of a survey grid above it, so the
+// ratio holds for any seed; the grid spans about a hundred continents.
+const CONTINENT_OCTAVES: u32 = 4;
+const CONTINENT_SURVEY_SIZE: usize = 64;
+const CONTINENT_SURVEY_SPACING: f32 = 1.63;
+
+/// Octaves of one noise function summed into a value in [0, 1]. `scale`
+/// converts from the noise coordinates of the base noise (world coordinates
+/// over `fineness`) to those of this source.
+pub struct NoiseSource {
+    noise: Box<dyn NoiseFn<[f64; 2]>>,
+    function: NoiseFunction,
+    scale: f32,
+    octaves: u32,
+    persistence: f32,
+    lacunarity: f32,
+    slope: f32,
+    max_possible_noise_value: f32,
+}
+
+impl NoiseSource {
+    pub fn new(
+        seed: u32,
+        function: NoiseFunction,
+        scale: f32,
+        octaves: u32,
+        persistence: f32,
+        lacunarity: f32,
+        slope: f32,
+    ) -> NoiseSource {
+        let noise: Box<dyn NoiseFn<[f64; 2]>> = match function {
+            // noise 0.7 exports two generators named `Perlin` through glob
+            // imports, so neither can be named. A single octave of its fBm
+            // is plain Perlin noise.
+            NoiseFunction::Perlin => Box::new(Fbm::new().set_seed(seed).set_octaves(1)),
+            NoiseFunction::OpenSimplex => Box::new(OpenSimplex::new().set_seed(seed)),
+            NoiseFunction::Worley => Box::new(Worley::new().set_seed(seed).enable_range(true)),
+            NoiseFunction::SuperSimplex | NoiseFunction::Ridged | NoiseFunction::Billow => {
+                Box::new(SuperSimplex::new().set_seed(seed))
+            }
+        };
+
+        let max_possible_noise_value = {
+            let mut max: f32 = 0.0;
+            let mut amplitude: f32 = 1.0;
+            for _ in 0..octaves {
+                max += amplitude;
+                amplitude *= persistence;
+            }
+            max
+        };
+
+        NoiseSource {
+            noise,
+            function,
+            scale,
+            octaves,
+            persistence,
+            lacunarity,
+            slope,
+            max_possible_noise_value,
+        }
+    }
+
+    pub fn sample(&self, noise_x: f32, noise_z: f32) -> f32 {
+        let noise_x = noise_x * self.scale;
+        let noise_z = noise_z * self.scale;
+        let mut amplitude: f32 = 1.0;
+        let mut frequency: f32 = 1.0;
+        let mut accumulated_noise_value: f32 = 0.0;
+        let mut ridge_weight: f32 = 1.0;
+        for _ in 0..self.octaves {
+            let sample_x = noise_x * frequency;
+            let sample_z = noise_z * frequency;
+            let sample = self.noise.get([sample_x as f64, (sample_z as f64)]);
+            let noise_value = match self.function {
+                NoiseFunction::Ridged => {
+                    let ridge = (1.0 - sample.abs() as f32).powi(2) * ridge_weight;
+                    ridge_weight = (ridge * RIDGE_GAIN).clamp(0.0, 1.0);
+                    ridge
+                }
+                NoiseFunction::Billow => sample.abs() as f32,
+                _ => (((1.0 + sample) / 2.0) as f32).clamp(0.0, 1.0),
+            };
+            accumulated_noise_value += noise_value.powf(self.slope) * amplitude;
+            amplitude *= self.persistence;
+            frequency *= self.lacunarity;
+        }
+        accumulated_noise_value / self.max_possible_noise_value
+    }
+}
+
+// Low frequency noise dividing the world into continents and oceans.
+struct Continents {
+    noise: NoiseSource,
+    coastline: f32,
+}
+
+impl Continents {
+    fn new(seed: u32, config: &TerrainConfig) -> Continents {
+        let noise = NoiseSource::new(
+            seed.wrapping_add(config.continent_seed_offset),
+            NoiseFunction::SuperSimplex,
+            1.0,
+            CONTINENT_OCTAVES,
+            0.5,
+            2.0,
+            1.0,
+        );
+        let mut survey: Vec<f32> = (0..CONTINENT_SURVEY_SIZE * CONTINENT_SURVEY_SIZE)
+            .map(|i| {
+                let x = (i % CONTINENT_SURVEY_SIZE) as f32 * CONTINENT_SURVEY_SPACING;
+                let z = (i / CONTINENT_SURVEY_SIZE) as f32 * CONTINENT_SURVEY_SPACING;
+                noise.sample(x, z)
+            })
+            .collect();
+        survey.sort_by(f32::total_cmp);
+        let ocean_count = ((1.0 - config.continent_land_ratio) * survey.len() as f32) as usize;
+        let coastline = survey[ocean_count.min(survey.len() - 1)];
+        Continents { noise, coastline }
+    }
+
+    // How far the point is raised (on land) or lowered (at sea), as a
+    // fraction of `max_height`.
+    fn offset(&self, x: f32, z: f32, config: &TerrainConfig) -> f32 {
+        let continentalness = self
+            .noise
+            .sample(x / config.continent_fineness, z / config.continent_fineness);
+        let shore =
+            ((continentalness - self.coastline) / config.continent_coast_width).clamp(-1.0, 1.0);
+        config.continent_amplitude
+            * shore.signum()
+            * shore.abs().powf(config.continent_coast_exponent)
+    }
+}
+
+// The noise of every biome with a height profile, and where each biome lies.
+struct BiomeHeights {
+    profiles: Vec<Option<NoiseSource>>,
+    grid: BiomeWeightGrid,
+}
+
+pub struct HeightSampler {
+    base: NoiseSource,
+    biomes: Option<BiomeHeights>,
+    graph: Option<CompiledHeightGraph>,
+    continents: Option<Continents>,
+    warp_simplexes: Vec<[SuperSimplex; 2]>,
+    config: TerrainConfig,
+}
+
+impl HeightSampler {
+    /// Samples `graph` instead of the noise described by `config` when given.
+    pub fn new(seed: u32, config: &TerrainConfig, graph: Option<&HeightGraph>) -> HeightSampler {
+        let base = NoiseSource::new(
+            seed,
+            config.noise_function,
+            1.0,
+            config.octaves,
+            config.persistence,
+            config.lacunarity,
+            config.noise_slope,
+        );
+        let graph = graph.map(|graph| graph.compile(seed, config));
+        let continents = if config.continent_fineness > 0.0 {
+            Some(Continents::new(seed, config))
+        } else {
+            None
+        };
+        let warp_simplexes = (0..config.warp_levels)
+            .map(|level| {
+                let seed = seed
+                    .wrapping_add(config.warp_seed_offset)
+                    .wrapping_add(level * 2);
+                [
+                    SuperSimplex::new().set_seed(seed),
+                    SuperSimplex::new().set_seed(seed.wrapping_add(1)),
+                ]
+            })
+            .collect();
+
+        HeightSampler {
+            base,
+            biomes: None,
+            graph,
+            continents,
+            warp_simplexes,
+            config: *config,
+        }
+    }
+
+    /// Shapes the terrain with the height profiles of the biomes in `table`,
+    /// which lie where `grid` says. Biomes without a profile keep the noise
+    /// described by the config or the height graph.
+    pub fn with_biomes(
+        self,
+        seed: u32,
+        table: &BiomeTable,
+        grid: BiomeWeightGrid,
+    ) -> HeightSampler {
+        let config = &self.config;
+        let profiles = table
+            .biomes()
+            .iter()
+            .map(|biome| {
+                biome.height_profile.map(|profile| {
+                    NoiseSource::new(
+                        seed,
+                        config.noise_function,
+                        1.0,
+                        profile.octaves.unwrap_or(config.octaves),
+                        profile.persistence.unwrap_or(config.persistence),
+                        profile.lacunarity.unwrap_or(config.lacunarity),
+                        profile.noise_slope.unwrap_or(config.noise_slope),
+                    )
+                })
+            })
+            .collect();
+        HeightSampler {
+            biomes: Some(BiomeHeights { profiles, grid }),
+            ..self
+        }
+    }
+
+    pub fn calculate_noise_height(&self, x: i32, z: i32) -> f32 {
+        self.height(x, z, true)
+    }
+
+    /// The height without biome height profiles.
+    pub fn calculate_base_height(&self, x: i32, z: i32) -> f32 {
+        self.height(x, z, false)
+    }
+
+    fn height(&self, x: i32, z: i32, with_biomes: bool) -> f32 {
+        let config = &self.config;
+        let (noise_x, noise_z) =
+            self.warp((x as f32) / config.fineness, (z as f32) / config.fineness);
+        let base_value = || match &self.graph {
+            Some(graph) => graph.evaluate(noise_x, noise_z),
+            None => self.base.sample(noise_x, noise_z),
+        };
+        let mut value = match &self.biomes {
+            Some(biomes) if with_biomes => {
+                let weights = biomes.grid.weights_at(x as f32, z as f32);
+                let mut value = 0.0;
+                let mut base_weight = 0.0;
+                for (weight, profile) in weights.zip(biomes.profiles.iter()) {
+                    match profile {
+                        Some(profile) if weight > 0.0 => {
+                            value += profile.sample(noise_x, noise_z) * weight
+                        }
+                        Some(_) => {}
+                        None => base_weight += weight,
+                    }
+                }
+                if base_weight > 0.0 {
+                    value += base_value() * base_weight;
+                }
+                value
+            }
+            _ => base_value(),
+        };
+        if let Some(continents) = &self.continents {
+            value = (value + continents.offset(x as f32, z as f32, config)).clamp(0.0, 1.0);
+        }
+        value * config.max_height
+    }
+
+    // Signed fBm in about [-1, 1] for displacing sample points.
+    fn warp_noise(simplex: &SuperSimplex, x: f32, z: f32) -> f32 {
+        let mut amplitude: f32 = 1.0;
+        let mut frequency: f32 = 1.0;
+        let mut total_amplitude: f32 = 0.0;
+        let mut accumulated_noise_value: f32 = 0.0;
+        for _ in 0..WARP_OCTAVES {
+            let noise_value = simplex.get([(x * frequency) as f64, (z * frequency) as f64]) as f32;
+            accumulated_noise_value += noise_value * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        accumulated_noise_value / total_amplitude
+    }
+
+    // Displaces a point in noise coordinates by `warp_amplitude` world units
+    // at most per level.
+    fn warp(&self, noise_x: f32, noise_z: f32) -> (f32, f32) {
+        let config = &self.config;
+        let amplitude = config.warp_amplitude / config.fineness;
+        let (mut offset_x, mut offset_z) = (0.0, 0.0);
+        for [simplex_x, simplex_z] in self.warp_simplexes.iter() {
+            let sample_x = (noise_x + offset_x) * config.warp_frequency;
+            let sample_z = (noise_z + offset_z) * config.warp_frequency;
+            offset_x = amplitude * HeightSampler::warp_noise(simplex_x, sample_x, sample_z);
+            offset_z = amplitude * HeightSampler::warp_noise(simplex_z, sample_x, sample_z);
+        }
+        (noise_x + offset_x, noise_z + offset_z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeightSampler, NoiseFunction};
+    use crate::TerrainConfig;
+
+    #[test]
+    fn every_noise_function_stays_in_range() {
+        let functions = [
+            NoiseFunction::SuperSimplex,
+            NoiseFunction::Perlin,
+            NoiseFunction::OpenSimplex,
+            NoiseFunction::Worley,
+            NoiseFunction::Ridged,
+            NoiseFunction::Billow,
+        ];
+        let mut first_heights = Vec::new();
+        for &noise_function in functions.iter() {
+            let config = TerrainConfig {
+                noise_function,
+                ..TerrainConfig::default()
+            };
+            let sampler = HeightSampler::new(11, &config, None);
+            for z in -20..20 {
+                for x in -20..20 {
+                    let height = sampler.calculate_noise_height(x * 37, z * 53);
+                    assert!((0.0..=config.max_height).contains(&height));
+                }
+            }
+            first_heights.push(sampler.calculate_noise_height(123, 456));
+        }
+        for (i, height) in first_heights.iter().enumerate() {
+            assert!(!first_heights[..i].contains(height));
+        }
+    }
+
+    #[test]
+    fn warping_moves_features_by_at_most_the_amplitude() {
+        let plain = HeightSampler::new(5, &TerrainConfig::default(), None);
+        for &warp_levels in [1, 2].iter() {
+            let config = TerrainConfig {
+                warp_levels,
+                warp_amplitude: 40.0,
+                ..TerrainConfig::default()
+            };
+            let warped = HeightSampler::new(5, &config, None);
+            let mut changed = false;
+            for &(x, z) in [(0, 0), (130, -70), (-900, 4000)].iter() {
+                let (noise_x, noise_z) = warped.warp(x as f32 / 512.0, z as f32 / 512.0);
+                let distance = (noise_x * 512.0 - x as f32).hypot(noise_z * 512.0 - z as f32);
+                assert!(distance <= 40.0 * 2.0f32.sqrt() + 1e-2);
+                changed |=
+                    warped.calculate_noise_height(x, z) != plain.calculate_noise_height(x, z);
+            }
+            assert!(changed);
+        }
+    }
+
+    #[test]
+    fn continents_cover_the_land_ratio() {
+        let plain = HeightSampler::new(9, &TerrainConfig::default(), None);
+        let config = TerrainConfig {
+            continent_fineness: 8192.0,
+            continent_land_ratio: 0.3,
+            ..TerrainConfig::default()
+        };
+        let sampler = HeightSampler::new(9, &config, None);
+        let continents = sampler.continents.as_ref().unwrap();
+        let mut land = 0;
+        for i in 0..2500 {
+            let (x, z) = ((i % 50 - 25) * 3001, (i / 50 - 25) * 2999);
+            let offset = continents.offset(x as f32, z as f32, &config);
+            assert!(offset.abs() <= config.continent_amplitude);
+            if offset > 0.0 {
+                land += 1;
+            }
+            let expected = (plain.calculate_noise_height(x, z) / config.max_height + offset)
+                .clamp(0.0, 1.0)
+                * config.max_height;
+            assert!((sampler.calculate_noise_height(x, z) - expected).abs() < 1e-3);
+        }
+        assert!((600..=900).contains(&land), "{}", land);
+    }
+}
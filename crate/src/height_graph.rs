@@ -0,0 +1,463 @@
+use crate::height::{NoiseFunction, NoiseSource};
+use crate::TerrainConfig;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// One node of a `HeightGraph`. Every node outputs a value per point, where
+/// 0 is the ground floor and 1 is `max_height`; inputs name other nodes.
+///
+/// * `noise` sums octaves of a noise function into [0, 1]. Parameters left
+///   out are taken from the `TerrainConfig`, and `seed_offset` is added to
+///   the world seed so every noise node can differ.
+/// * `constant` outputs `value` everywhere.
+/// * `curve` maps its input through a piecewise linear curve given as
+///   `[input, output]` points sorted by input, holding the end values
+///   outside them.
+/// * `add`, `multiply`, `min` and `max` combine any number of inputs.
+/// * `blend` mixes `a` into `b` as `mask` goes from 0 to 1.
+/// * `select` outputs `low` where `mask` is below `threshold` and `high`
+///   above it, blending the two within `falloff` of the threshold.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HeightNode {
+    Noise {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        function: Option<NoiseFunction>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fineness: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        octaves: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        persistence: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lacunarity: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        slope: Option<f32>,
+        #[serde(default)]
+        seed_offset: u32,
+    },
+    Constant {
+        value: f32,
+    },
+    Curve {
+        input: String,
+        points: Vec<[f32; 2]>,
+    },
+    Add {
+        inputs: Vec<String>,
+    },
+    Multiply {
+        inputs: Vec<String>,
+    },
+    Min {
+        inputs: Vec<String>,
+    },
+    Max {
+        inputs: Vec<String>,
+    },
+    Blend {
+        a: String,
+        b: String,
+        mask: String,
+    },
+    Select {
+        low: String,
+        high: String,
+        mask: String,
+        threshold: f32,
+        #[serde(default)]
+        falloff: f32,
+    },
+}
+
+impl HeightNode {
+    fn inputs(&self) -> Vec<&str> {
+        match self {
+            HeightNode::Noise { .. } | HeightNode::Constant { .. } => Vec::new(),
+            HeightNode::Curve { input, .. } => vec![input],
+            HeightNode::Add { inputs }
+            | HeightNode::Multiply { inputs }
+            | HeightNode::Min { inputs }
+            | HeightNode::Max { inputs } => inputs.iter().map(String::as_str).collect(),
+            HeightNode::Blend { a, b, mask } => vec![a, b, mask],
+            HeightNode::Select {
+                low, high, mask, ..
+            } => vec![low, high, mask],
+        }
+    }
+}
+
+/// Terrain height as a graph of named nodes, evaluated at every point in
+/// place of the noise described by the `TerrainConfig`. The height is the
+/// value of the `output` node clamped to [0, 1], times `max_height`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeightGraph {
+    nodes: BTreeMap<String, HeightNode>,
+    output: String,
+}
+
+impl Default for HeightGraph {
+    /// A single noise node, which generates the same heights as no graph.
+    fn default() -> HeightGraph {
+        let noise = HeightNode::Noise {
+            function: None,
+            fineness: None,
+            octaves: None,
+            persistence: None,
+            lacunarity: None,
+            slope: None,
+            seed_offset: 0,
+        };
+        HeightGraph {
+            nodes: BTreeMap::from([("height".to_string(), noise)]),
+            output: "height".to_string(),
+        }
+    }
+}
+
+impl HeightGraph {
+    pub fn new(nodes: BTreeMap<String, HeightNode>, output: &str) -> Result<HeightGraph, String> {
+        let graph = HeightGraph {
+            nodes,
+            output: output.to_string(),
+        };
+        graph.validate()?;
+        Ok(graph)
+    }
+
+    pub fn from_json(json: &str) -> Result<HeightGraph, String> {
+        let graph: HeightGraph = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        graph.validate()?;
+        Ok(graph)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn nodes(&self) -> &BTreeMap<String, HeightNode> {
+        &self.nodes
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, node) in self.nodes.iter() {
+            for input in node.inputs() {
+                if !self.nodes.contains_key(input) {
+                    return Err(format!("node {} reads unknown node {}", name, input));
+                }
+            }
+            let invalid = match node {
+                HeightNode::Noise {
+                    fineness,
+                    octaves,
+                    persistence,
+                    lacunarity,
+                    slope,
+                    ..
+                } => {
+                    // Parameters left out come from the config, which is
+                    // validated on its own.
+                    let persistence = persistence.unwrap_or(1.0);
+                    [
+                        (fineness.unwrap_or(1.0) > 0.0, "fineness must be positive"),
+                        (
+                            (1..=16).contains(&octaves.unwrap_or(1)),
+                            "octaves must be between 1 and 16",
+                        ),
+                        (
+                            persistence > 0.0 && persistence <= 1.0,
+                            "persistence must be in (0, 1]",
+                        ),
+                        (
+                            lacunarity.unwrap_or(1.0) >= 1.0,
+                            "lacunarity must be at least 1",
+                        ),
+                        (slope.unwrap_or(1.0) > 0.0, "slope must be positive"),
+                    ]
+                    .iter()
+                    .find(|(valid, _)| !valid)
+                    .map(|&(_, message)| message)
+                }
+                HeightNode::Constant { value } if !value.is_finite() => {
+                    Some("value must be finite")
+                }
+                HeightNode::Curve { points, .. } => {
+                    if points.is_empty() {
+                        Some("a curve needs at least one point")
+                    } else if points.iter().flatten().any(|value| !value.is_finite()) {
+                        Some("curve points must be finite")
+                    } else if points.windows(2).any(|pair| pair[0][0] > pair[1][0]) {
+                        Some("curve points must be sorted by input")
+                    } else {
+                        None
+                    }
+                }
+                HeightNode::Add { inputs }
+                | HeightNode::Multiply { inputs }
+                | HeightNode::Min { inputs }
+                | HeightNode::Max { inputs }
+                    if inputs.is_empty() =>
+                {
+                    Some("inputs must not be empty")
+                }
+                HeightNode::Select {
+                    threshold, falloff, ..
+                } => {
+                    if threshold.is_finite() && *falloff >= 0.0 {
+                        None
+                    } else {
+                        Some("threshold must be finite and falloff not negative")
+                    }
+                }
+                _ => None,
+            };
+            if let Some(message) = invalid {
+                return Err(format!("node {}: {}", name, message));
+            }
+        }
+        if !self.nodes.contains_key(&self.output) {
+            return Err(format!("output node {} does not exist", self.output));
+        }
+        self.order().map(|_| ())
+    }
+
+    // The nodes the output depends on, each after its inputs, or an error if
+    // they depend on themselves.
+    fn order(&self) -> Result<Vec<&str>, String> {
+        fn visit<'a>(
+            graph: &'a HeightGraph,
+            name: &'a str,
+            visiting: &mut Vec<&'a str>,
+            order: &mut Vec<&'a str>,
+        ) -> Result<(), String> {
+            if order.contains(&name) {
+                return Ok(());
+            }
+            if visiting.contains(&name) {
+                return Err(format!("node {} depends on itself", name));
+            }
+            visiting.push(name);
+            for input in graph.nodes[name].inputs() {
+                visit(graph, input, visiting, order)?;
+            }
+            visiting.pop();
+            order.push(name);
+            Ok(())
+        }
+
+        let mut order = Vec::new();
+        visit(self, &self.output, &mut Vec::new(), &mut order)?;
+        Ok(order)
+    }
+
+    pub(crate) fn compile(&self, seed: u32, config: &TerrainConfig) -> CompiledHeightGraph {
+        let order = self.order().unwrap();
+        let index = |name: &str| order.iter().position(|&other| other == name).unwrap();
+        let indices = |inputs: &[String]| inputs.iter().map(|input| index(input)).collect();
+        let nodes = order
+            .iter()
+            .map(|&name| match &self.nodes[name] {
+                HeightNode::Noise {
+                    function,
+                    fineness,
+                    octaves,
+                    persistence,
+                    lacunarity,
+                    slope,
+                    seed_offset,
+                } => CompiledNode::Noise(NoiseSource::new(
+                    seed.wrapping_add(*seed_offset),
+                    function.unwrap_or(config.noise_function),
+                    config.fineness / fineness.unwrap_or(config.fineness),
+                    octaves.unwrap_or(config.octaves),
+                    persistence.unwrap_or(config.persistence),
+                    lacunarity.unwrap_or(config.lacunarity),
+                    slope.unwrap_or(config.noise_slope),
+                )),
+                HeightNode::Constant { value } => CompiledNode::Constant(*value),
+                HeightNode::Curve { input, points } => {
+                    CompiledNode::Curve(index(input), points.clone())
+                }
+                HeightNode::Add { inputs } => CompiledNode::Add(indices(inputs)),
+                HeightNode::Multiply { inputs } => CompiledNode::Multiply(indices(inputs)),
+                HeightNode::Min { inputs } => CompiledNode::Min(indices(inputs)),
+                HeightNode::Max { inputs } => CompiledNode::Max(indices(inputs)),
+                HeightNode::Blend { a, b, mask } => {
+                    CompiledNode::Blend(index(a), index(b), index(mask))
+                }
+                HeightNode::Select {
+                    low,
+                    high,
+                    mask,
+                    threshold,
+                    falloff,
+                } => {
+                    CompiledNode::Select(index(low), index(high), index(mask), *threshold, *falloff)
+                }
+            })
+            .collect();
+        CompiledHeightGraph { nodes }
+    }
+}
+
+enum CompiledNode {
+    Noise(NoiseSource),
+    Constant(f32),
+    Curve(usize, Vec<[f32; 2]>),
+    Add(Vec<usize>),
+    Multiply(Vec<usize>),
+    Min(Vec<usize>),
+    Max(Vec<usize>),
+    Blend(usize, usize, usize),
+    Select(usize, usize, usize, f32, f32),
+}
+
+/// A `HeightGraph` with its noise sources seeded and its nodes sorted so
+/// that every node comes after its inputs and the output comes last.
+pub struct CompiledHeightGraph {
+    nodes: Vec<CompiledNode>,
+}
+
+fn curve_value(points: &[[f32; 2]], input: f32) -> f32 {
+    let after = points.iter().position(|point| point[0] > input);
+    match after {
+        Some(0) => points[0][1],
+        None => points[points.len() - 1][1],
+        Some(after) => {
+            let ([x0, y0], [x1, y1]) = (points[after - 1], points[after]);
+            y0 + (y1 - y0) * (input - x0) / (x1 - x0)
+        }
+    }
+}
+
+fn gather<'a>(values: &'a [f32], indices: &'a [usize]) -> impl Iterator<Item = f32> + 'a {
+    indices.iter().map(move |&index| values[index])
+}
+
+thread_local! {
+    // The node values of the sample being evaluated. Kept per thread rather
+    // than per graph so samplers stay shareable between threads, and reused
+    // so that evaluating a sample does not allocate.
+    static VALUES: RefCell<Vec<f32>> = const { RefCell::new(Vec::new()) };
+}
+
+impl CompiledHeightGraph {
+    /// The output in [0, 1] at a point in base noise coordinates.
+    pub fn evaluate(&self, noise_x: f32, noise_z: f32) -> f32 {
+        VALUES.with(|values| self.evaluate_into(&mut values.borrow_mut(), noise_x, noise_z))
+    }
+
+    fn evaluate_into(&self, values: &mut Vec<f32>, noise_x: f32, noise_z: f32) -> f32 {
+        values.clear();
+        for node in self.nodes.iter() {
+            let inputs = |indices| gather(values, indices);
+            let value = match node {
+                CompiledNode::Noise(source) => source.sample(noise_x, noise_z),
+                CompiledNode::Constant(value) => *value,
+                CompiledNode::Curve(input, points) => curve_value(points, values[*input]),
+                CompiledNode::Add(indices) => inputs(indices).sum(),
+                CompiledNode::Multiply(indices) => inputs(indices).product(),
+                CompiledNode::Min(indices) => inputs(indices).fold(f32::INFINITY, f32::min),
+                CompiledNode::Max(indices) => inputs(indices).fold(f32::NEG_INFINITY, f32::max),
+                CompiledNode::Blend(a, b, mask) => {
+                    let mask = values[*mask].clamp(0.0, 1.0);
+                    values[*a] * (1.0 - mask) + values[*b] * mask
+                }
+                CompiledNode::Select(low, high, mask, threshold, falloff) => {
+                    let mask = values[*mask];
+                    let weight = if *falloff > 0.0 {
+                        ((mask - (threshold - falloff)) / (2.0 * falloff)).clamp(0.0, 1.0)
+                    } else if mask < *threshold {
+                        0.0
+                    } else {
+                        1.0
+                    };
+                    values[*low] * (1.0 - weight) + values[*high] * weight
+                }
+            };
+            values.push(value);
+        }
+        values.last().unwrap().clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeightGraph;
+    use crate::height::HeightSampler;
+    use crate::TerrainConfig;
+
+    const CONTINENTS: &str = r#"{
+        "nodes": {
+            "mask": { "type": "noise", "fineness": 4096, "octaves": 2, "seed_offset": 7 },
+            "plains": { "type": "curve", "input": "hills", "points": [[0, 0.35], [1, 0.45]] },
+            "hills": { "type": "noise", "function": "billow", "octaves": 3 },
+            "mountains": { "type": "noise", "function": "ridged", "seed_offset": 1 },
+            "land": { "type": "blend", "a": "plains", "b": "mountains", "mask": "mask" },
+            "sea": { "type": "constant", "value": 0.2 },
+            "height": {
+                "type": "select", "low": "sea", "high": "land", "mask": "mask",
+                "threshold": 0.3, "falloff": 0.05
+            }
+        },
+        "output": "height"
+    }"#;
+
+    #[test]
+    fn default_graph_matches_the_config_noise() {
+        let config = TerrainConfig::default();
+        let plain = HeightSampler::new(3, &config, None);
+        let graph = HeightSampler::new(3, &config, Some(&HeightGraph::default()));
+        for &(x, z) in [(0, 0), (17, -300), (-5000, 1234)].iter() {
+            assert_eq!(
+                plain.calculate_noise_height(x, z),
+                graph.calculate_noise_height(x, z)
+            );
+        }
+    }
+
+    #[test]
+    fn evaluates_a_layered_graph() {
+        let graph = HeightGraph::from_json(CONTINENTS).unwrap();
+        assert_eq!(HeightGraph::from_json(&graph.to_json()).unwrap(), graph);
+
+        let config = TerrainConfig::default();
+        let sampler = HeightSampler::new(3, &config, Some(&graph));
+        let heights: Vec<f32> = (0..400)
+            .map(|i| sampler.calculate_noise_height((i % 20) * 401, (i / 20) * 389))
+            .collect();
+        assert!(heights
+            .iter()
+            .all(|height| (0.0..=config.max_height).contains(height)));
+        let sea = heights
+            .iter()
+            .filter(|&&height| height == 0.2 * config.max_height)
+            .count();
+        assert!(sea > 0 && sea < heights.len());
+    }
+
+    #[test]
+    fn rejects_broken_graphs() {
+        let cycle = r#"{ "nodes": {
+            "a": { "type": "add", "inputs": ["b"] },
+            "b": { "type": "multiply", "inputs": ["a"] }
+        }, "output": "a" }"#;
+        assert!(HeightGraph::from_json(cycle).is_err());
+        let unknown = r#"{ "nodes": { "a": { "type": "curve", "input": "b", "points": [[0, 0]] } },
+            "output": "a" }"#;
+        assert!(HeightGraph::from_json(unknown).is_err());
+        let unsorted = r#"{ "nodes": {
+            "n": { "type": "noise" },
+            "a": { "type": "curve", "input": "n", "points": [[1, 0], [0, 1]] }
+        }, "output": "a" }"#;
+        assert!(HeightGraph::from_json(unsorted).is_err());
+    }
+}
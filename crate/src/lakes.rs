@@ -0,0 +1,400 @@
+use crate::{max, min, TerrainConfig};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// Depression analysis with the priority-flood algorithm (Barnes, Lehman and
+// Mulla, 2014). Water drains off the edges of the chunk and into the sea, so
+// those cells seed a queue ordered by water level. Popping the lowest cell
+// and raising each unvisited neighbour to at least its level floods every
+// depression up to the height of its spill point. Flooded cells that touch
+// share the same level and form one basin. Filled depressions instead rise
+// by the smallest step from each cell to the next away from the spill point
+// (priority-flood+ε), so water still finds its way across them.
+
+/// What happens to the depressions of a chunk. `Fill` raises every
+/// depression to its spill point, leaving ground that slopes imperceptibly
+/// towards it. `Lakes` turns the
+/// depressions at least `lake_min_depth` deep into lakes and fills the
+/// shallower ones.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LakeMode {
+    #[default]
+    Off,
+    Fill,
+    Lakes,
+}
+
+/// A lake in world coordinates. `level` is the height of its water surface,
+/// `depth` how far the deepest point lies below it and `area` the number of
+/// cells it covers. Each outline is a closed loop of `[x, z]` points along
+/// the edges of the lake's cells, the first being the shore and any others
+/// islands; the last point repeats the first.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Lake {
+    pub level: f32,
+    pub depth: f32,
+    pub area: u32,
+    pub outlines: Vec<Vec<[f32; 2]>>,
+}
+
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(PartialEq)]
+struct QueuedCell {
+    level: f32,
+    index: usize,
+}
+
+impl Eq for QueuedCell {}
+
+impl Ord for QueuedCell {
+    // Reversed, so the binary heap pops the lowest level first.
+    fn cmp(&self, other: &QueuedCell) -> Ordering {
+        other
+            .level
+            .total_cmp(&self.level)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for QueuedCell {
+    fn partial_cmp(&self, other: &QueuedCell) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn neighbours(index: usize, width: u32, depth: u32) -> impl Iterator<Item = usize> {
+    let row = width as isize + 1;
+    let (x, z) = (index as isize % row, index as isize / row);
+    NEIGHBOURS.iter().filter_map(move |&(dx, dz)| {
+        let (neighbour_x, neighbour_z) = (x + dx, z + dz);
+        if neighbour_x < 0
+            || neighbour_z < 0
+            || neighbour_x > width as isize
+            || neighbour_z > depth as isize
+        {
+            None
+        } else {
+            Some((neighbour_z * row + neighbour_x) as usize)
+        }
+    })
+}
+
+// The smallest step above `value` that survives rounding.
+fn step_up(value: f32) -> f32 {
+    value + max(value.abs() * f32::EPSILON, f32::MIN_POSITIVE)
+}
+
+/// The height water would settle at on every entry of `height_map`, which
+/// is the entry's own height unless it lies in a depression.
+pub fn water_levels(height_map: &[f32], width: u32, depth: u32, sea_height: f32) -> Vec<f32> {
+    flood(height_map, width, depth, sea_height, false)
+}
+
+// With `sloped`, every cell that is not above its lowest flooded neighbour
+// rises a step above it instead of to its level, draining flats as well as
+// depressions.
+fn flood(height_map: &[f32], width: u32, depth: u32, sea_height: f32, sloped: bool) -> Vec<f32> {
+    let row = width as usize + 1;
+    let mut levels = height_map.to_vec();
+    let mut visited = vec![false; height_map.len()];
+    let mut queue = BinaryHeap::new();
+    for (index, &height) in height_map.iter().enumerate() {
+        let (x, z) = (index % row, index / row);
+        if x == 0 || z == 0 || x == width as usize || z == depth as usize || height <= sea_height {
+            visited[index] = true;
+            queue.push(QueuedCell {
+                level: height,
+                index,
+            });
+        }
+    }
+    while let Some(QueuedCell { level, index }) = queue.pop() {
+        for neighbour in neighbours(index, width, depth) {
+            if !visited[neighbour] {
+                visited[neighbour] = true;
+                levels[neighbour] = if sloped && height_map[neighbour] <= step_up(level) {
+                    step_up(level)
+                } else {
+                    max(height_map[neighbour], level)
+                };
+                queue.push(QueuedCell {
+                    level: levels[neighbour],
+                    index: neighbour,
+                });
+            }
+        }
+    }
+    levels
+}
+
+// Chains the cell edges between a basin and the rest of the chunk into
+// closed loops. Corner (i, j) lies half a cell before entry (i, j) on both
+// axes, and every edge runs clockwise around the basin's cells as seen from
+// above with z pointing down, so the loops never cross.
+fn trace_outlines(
+    cells: &[usize],
+    in_basin: &dyn Fn(usize, isize, isize) -> bool,
+    width: u32,
+    offset: (f32, f32),
+) -> Vec<Vec<[f32; 2]>> {
+    let row = width as usize + 1;
+    let mut edges: HashMap<(usize, usize), Vec<(usize, usize)>> = HashMap::new();
+    for &cell in cells {
+        let (x, z) = (cell % row, cell / row);
+        let sides = [
+            ((0, -1), (x, z), (x + 1, z)),
+            ((1, 0), (x + 1, z), (x + 1, z + 1)),
+            ((0, 1), (x + 1, z + 1), (x, z + 1)),
+            ((-1, 0), (x, z + 1), (x, z)),
+        ];
+        for &((dx, dz), from, to) in sides.iter() {
+            if !in_basin(cell, dx, dz) {
+                edges.entry(from).or_default().push(to);
+            }
+        }
+    }
+
+    let mut starts: Vec<(usize, usize)> = edges.keys().copied().collect();
+    starts.sort_unstable_by_key(|&(i, j)| (j, i));
+    let mut outlines = Vec::new();
+    let point = |(i, j): (usize, usize)| [i as f32 - 0.5 + offset.0, j as f32 - 0.5 + offset.1];
+    for start in starts {
+        while let Some(mut next) = edges.get_mut(&start).and_then(Vec::pop) {
+            let mut outline = vec![point(start), point(next)];
+            while next != start {
+                next = edges.get_mut(&next).and_then(Vec::pop).unwrap();
+                outline.push(point(next));
+            }
+            outlines.push(outline);
+        }
+    }
+    outlines
+}
+
+/// Fills or floods the depressions of `height_map` according to
+/// `lake_mode`. The map holds a chunk and `margin` entries around it, so
+/// depressions cut by the chunk edges are filled alike on both sides. Returns
+/// the lakes reaching into the chunk, whole, and a map laid out like
+/// `height_map` holding the water level of every lake entry and 0 elsewhere;
+/// both are empty unless the mode is `Lakes`.
+pub fn generate_lakes(
+    height_map: &mut [f32],
+    width: u32,
+    depth: u32,
+    chunk_x: i32,
+    chunk_z: i32,
+    margin: u32,
+    config: &TerrainConfig,
+) -> (Vec<Lake>, Vec<f32>) {
+    let (chunk_width, chunk_depth) = (width, depth);
+    let (width, depth) = (width + 2 * margin, depth + 2 * margin);
+    let sea_height = config.sea_level * config.max_height;
+    let filled = flood(height_map, width, depth, sea_height, true);
+    if config.lake_mode == LakeMode::Fill {
+        height_map.copy_from_slice(&filled);
+        return (Vec::new(), Vec::new());
+    }
+    let levels = water_levels(height_map, width, depth, sea_height);
+
+    let row = width as usize + 1;
+    let offset = (
+        (chunk_x * chunk_width as i32 - margin as i32) as f32,
+        (chunk_z * chunk_depth as i32 - margin as i32) as f32,
+    );
+    let in_chunk = |cell: usize| {
+        let (x, z) = ((cell % row) as u32, (cell / row) as u32);
+        (margin..=margin + chunk_width).contains(&x) && (margin..=margin + chunk_depth).contains(&z)
+    };
+    let mut basin_of = vec![usize::MAX; height_map.len()];
+    let mut lake_level_map = vec![0.0; height_map.len()];
+    let mut lakes = Vec::new();
+    let mut basin_count = 0;
+    for start in 0..height_map.len() {
+        if levels[start] <= height_map[start] || basin_of[start] != usize::MAX {
+            continue;
+        }
+        let basin = basin_count;
+        basin_count += 1;
+        basin_of[start] = basin;
+        let mut cells = vec![start];
+        let mut next = 0;
+        while next < cells.len() {
+            let cell = cells[next];
+            next += 1;
+            for neighbour in neighbours(cell, width, depth) {
+                if levels[neighbour] > height_map[neighbour] && basin_of[neighbour] == usize::MAX {
+                    basin_of[neighbour] = basin;
+                    cells.push(neighbour);
+                }
+            }
+        }
+
+        let level = levels[start];
+        let lowest = cells
+            .iter()
+            .fold(level, |lowest, &cell| min(lowest, height_map[cell]));
+        if level - lowest < config.lake_min_depth {
+            for &cell in cells.iter() {
+                height_map[cell] = filled[cell];
+            }
+            continue;
+        }
+        if !cells.iter().any(|&cell| in_chunk(cell)) {
+            continue;
+        }
+        let in_basin = |cell: usize, dx: isize, dz: isize| {
+            let (x, z) = ((cell % row) as isize + dx, (cell / row) as isize + dz);
+            x >= 0
+                && z >= 0
+                && x <= width as isize
+                && z <= depth as isize
+                && basin_of[z as usize * row + x as usize] == basin
+        };
+        for &cell in cells.iter() {
+            lake_level_map[cell] = level;
+        }
+        lakes.push(Lake {
+            level,
+            depth: level - lowest,
+            area: cells.len() as u32,
+            outlines: trace_outlines(&cells, &in_basin, width, offset),
+        });
+    }
+    (lakes, lake_level_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_lakes, water_levels, LakeMode};
+    use crate::{flow_accumulation, FlowRouting, TerrainConfig};
+
+    // A plane sloping towards x = 0 with a 2 deep pit of 3x3 cells at
+    // (10, 8) and a 0.1 deep one at (4, 4).
+    fn pitted_plane() -> Vec<f32> {
+        (0..17 * 17)
+            .map(|index| {
+                let (x, z) = (index % 17, index / 17);
+                let height = 200.0 + x as f32;
+                if (9..=11).contains(&x) && (7..=9).contains(&z) {
+                    206.0
+                } else if (x, z) == (4, 4) {
+                    height - 1.1
+                } else {
+                    height
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn floods_depressions_to_their_spill_point() {
+        let height_map = pitted_plane();
+        let levels = water_levels(&height_map, 16, 16, 0.0);
+        assert_eq!(levels[8 * 17 + 10], 208.0);
+        assert_eq!(levels[4 * 17 + 4], 203.0);
+        assert_eq!(levels[3], height_map[3]);
+    }
+
+    #[test]
+    fn turns_deep_depressions_into_lakes_and_fills_the_rest() {
+        let config = TerrainConfig {
+            lake_mode: LakeMode::Lakes,
+            lake_min_depth: 0.5,
+            sea_level: 0.0,
+            ..TerrainConfig::default()
+        };
+        let mut height_map = pitted_plane();
+        let original = height_map.clone();
+        let (lakes, lake_level_map) = generate_lakes(&mut height_map, 16, 16, 0, 1, 0, &config);
+
+        assert_eq!(lakes.len(), 1);
+        let lake = &lakes[0];
+        assert_eq!((lake.level, lake.depth, lake.area), (208.0, 2.0, 9));
+        assert_eq!(lake.outlines.len(), 1);
+        let outline = &lake.outlines[0];
+        assert_eq!(outline.len(), 13);
+        assert_eq!(outline.first(), outline.last());
+        assert!(outline
+            .iter()
+            .all(|&[x, z]| (8.5..=11.5).contains(&x) && (22.5..=25.5).contains(&z)));
+
+        assert_eq!(lake_level_map[8 * 17 + 10], 208.0);
+        assert_eq!(lake_level_map[8 * 17 + 12], 0.0);
+        assert_eq!(height_map[8 * 17 + 10], original[8 * 17 + 10]);
+        assert!((height_map[4 * 17 + 4] - 203.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn a_lake_across_the_chunk_edge_is_found_alike_on_both_sides() {
+        let config = TerrainConfig {
+            lake_mode: LakeMode::Lakes,
+            sea_level: 0.0,
+            ..TerrainConfig::default()
+        };
+        // A plane rising towards z with a pit of 3x3 cells around (16, 8),
+        // on the edge between chunks 0 and 1.
+        let height = |x: i32, z: i32| {
+            if (15..=17).contains(&x) && (7..=9).contains(&z) {
+                200.0
+            } else {
+                200.0 + z as f32
+            }
+        };
+        let chunk = |chunk_x: i32| {
+            let mut height_map: Vec<f32> = (0..25 * 25)
+                .map(|index| height(chunk_x * 16 - 4 + index % 25, index / 25 - 4))
+                .collect();
+            generate_lakes(&mut height_map, 16, 16, chunk_x, 0, 4, &config)
+        };
+        let (left_lakes, left_levels) = chunk(0);
+        let (right_lakes, right_levels) = chunk(1);
+
+        assert_eq!(left_lakes.len(), 1);
+        assert_eq!(left_lakes, right_lakes);
+        assert_eq!((left_lakes[0].level, left_lakes[0].area), (206.0, 9));
+        for z in 4..=20 {
+            assert_eq!(left_levels[z * 25 + 20], right_levels[z * 25 + 4]);
+        }
+        assert_eq!(left_levels[12 * 25 + 20], 206.0);
+    }
+
+    #[test]
+    fn rivers_run_through_filled_depressions() {
+        let config = TerrainConfig {
+            lake_mode: LakeMode::Fill,
+            sea_level: 0.0,
+            ..TerrainConfig::default()
+        };
+        // A valley along z = 8 draining towards x = 0, dammed by a 3x3 pit.
+        let mut height_map: Vec<f32> = (0..17 * 17)
+            .map(|index| {
+                let (x, z) = (index % 17, index / 17);
+                if (7..=9).contains(&x) && (7..=9).contains(&z) {
+                    100.0
+                } else {
+                    100.0 + x as f32 * 0.5 + (z as f32 - 8.0).abs()
+                }
+            })
+            .collect();
+        generate_lakes(&mut height_map, 16, 16, 0, 0, 0, &config);
+        assert!(height_map[8 * 17 + 8] < 103.5 + 0.01);
+        for &routing in [FlowRouting::D8, FlowRouting::DInfinity].iter() {
+            let accumulation = flow_accumulation(&height_map, 16, 16, routing);
+            assert!(accumulation[8 * 17] > 200.0, "{:?}", routing);
+        }
+    }
+}
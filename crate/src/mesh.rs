@@ -0,0 +1,626 @@
+use crate::{max, min, Chunk};
+
+// Heights just outside the chunk are read through `border_height`, which takes
+// world coordinates. Besides vertices and normals this returns the curvature
+// at every vertex: the average height of its four neighbours minus its own
+// height, positive in gullies and negative on ridges.
+pub fn build_surface(
+    height_map: &[f32],
+    width: u32,
+    depth: u32,
+    chunk_x: i32,
+    chunk_z: i32,
+    border_height: &dyn Fn(i32, i32) -> f32,
+) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let chunk_offset_x = chunk_x * width as i32;
+    let chunk_offset_z = chunk_z * depth as i32;
+    let vertex_array_length = ((width + 1) * (depth + 1) * 3) as usize;
+    let mut vertices = vec![0.0; vertex_array_length];
+    let mut normals = vec![0.0; vertex_array_length];
+    let mut curvatures = Vec::with_capacity(vertex_array_length / 3);
+
+    let mut p = 0;
+    let mut p2 = 0;
+    for z in 0..=depth {
+        for x in 0..=width {
+            let height = height_map[p2];
+            let left = if x == 0 {
+                border_height((x as i32 + chunk_offset_x) - 1, z as i32 + chunk_offset_z)
+            } else {
+                height_map[p2 - 1]
+            };
+            let right = if x == width {
+                border_height((x as i32 + chunk_offset_x) + 1, z as i32 + chunk_offset_z)
+            } else {
+                height_map[p2 + 1]
+            };
+            let top = if z == 0 {
+                border_height(x as i32 + chunk_offset_x, (z as i32 + chunk_offset_z) - 1)
+            } else {
+                height_map[p2 - (width + 1) as usize]
+            };
+            let bottom = if z == depth {
+                border_height(x as i32 + chunk_offset_x, (z as i32 + chunk_offset_z) + 1)
+            } else {
+                height_map[p2 + (width + 1) as usize]
+            };
+            let top_left = if x == 0 || z == 0 {
+                border_height(
+                    (x as i32 + chunk_offset_x) - 1,
+                    (z as i32 + chunk_offset_z) - 1,
+                )
+            } else {
+                height_map[p2 - 1 - (width + 1) as usize]
+            };
+            let bottom_right = if x == width || z == depth {
+                border_height(
+                    (x as i32 + chunk_offset_x) + 1,
+                    (z as i32 + chunk_offset_z) + 1,
+                )
+            } else {
+                height_map[p2 + 1 + (width + 1) as usize]
+            };
+            p2 += 1;
+            curvatures.push((left + right + top + bottom) / 4.0 - height);
+            let mut norm_x = 2.0 * (left - right) - bottom_right + top_left + bottom - top;
+            let mut norm_y: f32 = 6.0;
+            let mut norm_z = 2.0 * (top - bottom) + bottom_right + top_left - bottom - left;
+            let len2 = norm_x.powi(2) + norm_y.powi(2) + norm_z.powi(2);
+            let scale = 1.0 / len2.sqrt();
+            norm_x *= scale;
+            norm_y *= scale;
+            norm_z *= scale;
+            normals[p] = norm_x;
+            vertices[p] = (x as i32 + chunk_offset_x) as f32;
+            p += 1;
+            normals[p] = norm_y;
+            vertices[p] = height;
+            p += 1;
+            normals[p] = norm_z;
+            vertices[p] = (z as i32 + chunk_offset_z) as f32;
+            p += 1;
+        }
+    }
+
+    (vertices, normals, curvatures)
+}
+
+pub fn build_indices(width: u32, depth: u32) -> Vec<u32> {
+    let mut indices = vec![0; (width * depth * 6) as usize];
+    let mut p = 0;
+    let mut p2 = 0;
+    for _ in 0..depth {
+        for _ in 0..width {
+            indices[p] = p2 as u32;
+            p += 1;
+            indices[p] = p2 as u32 + (width + 1);
+            p += 1;
+            indices[p] = p2 as u32 + 1;
+            p += 1;
+            indices[p] = p2 as u32 + (width + 1);
+            p += 1;
+            indices[p] = p2 as u32 + 1 + (width + 1);
+            p += 1;
+            indices[p] = p2 as u32 + 1;
+            p += 1;
+            p2 += 1;
+        }
+        p2 += 1;
+    }
+
+    indices
+}
+
+/// A simplified mesh of a chunk, whose vertices are a subset of its height
+/// map entries followed by a skirt: one vertex `skirt_depth` below every
+/// vertex on the edges of the chunk, walking around it. The skirt hides the
+/// gaps along the edges between neighbouring chunks simplified differently.
+/// Normals, colors and `splat_layer_count` splat weights are those of the
+/// height map entries.
+pub struct ChunkMesh {
+    pub skirt_depth: f32,
+    pub vertices: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub colors: Vec<f32>,
+    pub splat_weights: Vec<f32>,
+    pub splat_layer_count: u32,
+    pub indices: Vec<u32>,
+}
+
+fn max_lod_level(width: u32, depth: u32) -> u32 {
+    min(width, depth).trailing_zeros()
+}
+
+pub fn check_lod_level(width: u32, depth: u32, level: u32) -> Result<(), String> {
+    let max_level = max_lod_level(width, depth);
+    if level > max_level {
+        return Err(format!(
+            "level must be at most {} for a {}x{} chunk",
+            max_level, width, depth
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_max_error(max_error: f32) -> Result<(), String> {
+    if max_error < 0.0 || !max_error.is_finite() {
+        return Err("max_error must be a non-negative number".to_string());
+    }
+    Ok(())
+}
+
+// Keeps every `stride`th height map entry along both axes, where `stride`
+// is 2 to the power of `level`, with the grid vertices row by row.
+pub fn build_lod_mesh(chunk: &Chunk, level: u32) -> Result<ChunkMesh, String> {
+    check_lod_level(chunk.width, chunk.depth, level)?;
+    let stride = 1 << level;
+    let row = chunk.width as usize + 1;
+    let cells_x = chunk.width / stride;
+    let cells_z = chunk.depth / stride;
+
+    let mut entries = Vec::with_capacity(((cells_x + 1) * (cells_z + 1)) as usize);
+    for z in 0..=cells_z {
+        for x in 0..=cells_x {
+            entries.push((z * stride) as usize * row + (x * stride) as usize);
+        }
+    }
+    let grid_row = cells_x + 1;
+    let mut perimeter = Vec::with_capacity(2 * (cells_x + cells_z) as usize);
+    perimeter.extend(0..cells_x);
+    perimeter.extend((0..cells_z).map(|z| z * grid_row + cells_x));
+    perimeter.extend((1..=cells_x).rev().map(|x| cells_z * grid_row + x));
+    perimeter.extend((1..=cells_z).rev().map(|z| z * grid_row));
+
+    let skirt_depth = lod_skirt_depth(&chunk.height_map, chunk.width, chunk.depth);
+    let indices = build_indices(cells_x, cells_z);
+    Ok(assemble_mesh(
+        chunk,
+        &entries,
+        indices,
+        &perimeter,
+        skirt_depth,
+    ))
+}
+
+// Along an edge, a chunk meshed at a coarser level than its neighbour sits
+// above or below the neighbour's vertices by at most the largest distance
+// between the edge's heights and its coarser outline. The edge is shared, so
+// the largest such distance over every edge and level hides every gap from
+// either side, and the skirt is equally deep at every level.
+fn lod_skirt_depth(height_map: &[f32], width: u32, depth: u32) -> f32 {
+    let mut deepest = 0.0;
+    for level in 1..=max_lod_level(width, depth) {
+        let stride = 1 << level;
+        for edge in edges(height_map, width, depth).iter() {
+            let kept: Vec<bool> = (0..edge.len()).map(|i| i % stride == 0).collect();
+            deepest = max(deepest, outline_distance(edge, &kept));
+        }
+    }
+    deepest
+}
+
+// A right-triangulated irregular network: every triangle is split in two
+// through the middle of its longest edge until the heights inside it lie
+// within `max_error` of it. The chunk is covered by square tiles as long as
+// its shorter side, each split along its diagonal first. The error of
+// leaving out an entry is the largest distance of the heights from either
+// triangle it splits, and at least the errors of the entries splitting
+// their halves, so a triangle is only split together with the neighbour
+// across its longest edge and the mesh has no T-junctions, within and
+// between the tiles.
+pub fn build_adaptive_mesh(chunk: &Chunk, max_error: f32) -> Result<ChunkMesh, String> {
+    check_max_error(max_error)?;
+    let (width, depth) = (chunk.width as usize, chunk.depth as usize);
+    let row = width + 1;
+    let size = min(width, depth);
+    let tiles: Vec<(usize, usize)> = (0..depth / size)
+        .flat_map(|tile_z| (0..width / size).map(move |tile_x| (tile_x * size, tile_z * size)))
+        .collect();
+    let heights = &chunk.height_map;
+
+    // Triangle `id` has children `2 * id` and `2 * id + 1`, down to the
+    // smallest triangles spanning two cells, so visiting the ids backwards
+    // finishes every entry's error before it is needed.
+    let triangle_count = size * size * 2 - 2;
+    let parent_count = triangle_count - size * size;
+    let mut errors = vec![0.0; heights.len()];
+    for i in (0..triangle_count).rev() {
+        let (a, b) = triangle_corners(i + 2, size);
+        let middle = ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+        let apex = (middle.0 + middle.1 - a.1, middle.1 + a.0 - middle.0);
+        for &(offset_x, offset_z) in tiles.iter() {
+            let at = |(x, z): (usize, usize)| (z + offset_z) * row + x + offset_x;
+            let middle_index = at(middle);
+            let corners = [at(a), at(b), at(apex)];
+            let mut error = max(errors[middle_index], triangle_error(corners, heights, row));
+            if i < parent_count {
+                let left = at(((a.0 + apex.0) / 2, (a.1 + apex.1) / 2));
+                let right = at(((b.0 + apex.0) / 2, (b.1 + apex.1) / 2));
+                error = max(error, max(errors[left], errors[right]));
+            }
+            errors[middle_index] = error;
+        }
+    }
+
+    let mut triangles = Vec::new();
+    for &(offset_x, offset_z) in tiles.iter() {
+        let corner = |x: usize, z: usize| (x + offset_x, z + offset_z);
+        let (near, far) = (corner(0, 0), corner(size, size));
+        for (a, b, c) in [(near, far, corner(size, 0)), (far, near, corner(0, size))] {
+            split_triangle(a, b, c, &errors, row, max_error, &mut triangles);
+        }
+    }
+
+    // Number the entries the triangles use in height map order.
+    let mut vertex_of = vec![u32::MAX; heights.len()];
+    for &entry in triangles.iter() {
+        vertex_of[entry] = 0;
+    }
+    let mut entries = Vec::new();
+    for (entry, vertex) in vertex_of.iter_mut().enumerate() {
+        if *vertex != u32::MAX {
+            *vertex = entries.len() as u32;
+            entries.push(entry);
+        }
+    }
+    let indices = triangles.iter().map(|&entry| vertex_of[entry]).collect();
+    let perimeter: Vec<u32> = (0..width)
+        .chain((0..depth).map(|z| z * row + width))
+        .chain((1..=width).rev().map(|x| depth * row + x))
+        .chain((1..=depth).rev().map(|z| z * row))
+        .map(|entry| vertex_of[entry])
+        .filter(|&vertex| vertex != u32::MAX)
+        .collect();
+
+    // The edges of both neighbours pass through the shared heights they keep,
+    // and an edge at most `max_error` from every height it leaves out at its
+    // middle strays by less than twice that between them, so this skirt
+    // reaches any neighbour meshed with the same or a smaller error.
+    let kept: Vec<bool> = vertex_of.iter().map(|&vertex| vertex != u32::MAX).collect();
+    let mut outline = 0.0;
+    for (edge, kept) in edges(heights, chunk.width, chunk.depth)
+        .iter()
+        .zip(edges(&kept, chunk.width, chunk.depth).iter())
+    {
+        outline = max(outline, outline_distance(edge, kept));
+    }
+    let skirt_depth = outline + 2.0 * max_error;
+    Ok(assemble_mesh(
+        chunk,
+        &entries,
+        indices,
+        &perimeter,
+        skirt_depth,
+    ))
+}
+
+// The ends of the longest edge of triangle `id` of a tile of the given
+// size, where 2 and 3 split the tile along its diagonal.
+fn triangle_corners(mut id: usize, size: usize) -> ((usize, usize), (usize, usize)) {
+    let (mut a, mut b, mut c) = if id & 1 == 1 {
+        ((0, 0), (size, size), (size, 0))
+    } else {
+        ((size, size), (0, 0), (0, size))
+    };
+    loop {
+        id >>= 1;
+        if id <= 1 {
+            break;
+        }
+        let middle = ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+        if id & 1 == 1 {
+            (a, b) = (c, a);
+        } else {
+            (a, b) = (b, c);
+        }
+        c = middle;
+    }
+    (a, b)
+}
+
+// The largest distance between the heights inside the triangle with the
+// given corner entries and the plane through its corners.
+fn triangle_error(corners: [usize; 3], heights: &[f32], row: usize) -> f32 {
+    let [a, b, c] = corners.map(|entry| ((entry % row) as isize, (entry / row) as isize));
+    let [height_a, height_b, height_c] = corners.map(|entry| heights[entry]);
+    let (ab_x, ab_z) = (b.0 - a.0, b.1 - a.1);
+    let (ac_x, ac_z) = (c.0 - a.0, c.1 - a.1);
+    let area = ab_x * ac_z - ab_z * ac_x;
+    let mut error = 0.0;
+    for z in min(a.1, min(b.1, c.1))..=max(a.1, max(b.1, c.1)) {
+        for x in min(a.0, min(b.0, c.0))..=max(a.0, max(b.0, c.0)) {
+            // The barycentric coordinates of the entry, times the area.
+            let (offset_x, offset_z) = (x - a.0, z - a.1);
+            let u = (offset_x * ac_z - offset_z * ac_x) * area.signum();
+            let v = (ab_x * offset_z - ab_z * offset_x) * area.signum();
+            if u >= 0 && v >= 0 && u + v <= area.abs() {
+                let (u, v) = (u as f32 / area.abs() as f32, v as f32 / area.abs() as f32);
+                let plane = height_a + (height_b - height_a) * u + (height_c - height_a) * v;
+                error = max(
+                    error,
+                    (heights[z as usize * row + x as usize] - plane).abs(),
+                );
+            }
+        }
+    }
+    error
+}
+
+// Splits the triangle through the middle of its longest edge `a` to `b`
+// while the error of leaving out the middle is too large, keeping the
+// winding of `build_indices`.
+fn split_triangle(
+    a: (usize, usize),
+    b: (usize, usize),
+    c: (usize, usize),
+    errors: &[f32],
+    row: usize,
+    max_error: f32,
+    triangles: &mut Vec<usize>,
+) {
+    let middle = ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+    if a.0.abs_diff(c.0) + a.1.abs_diff(c.1) > 1 && errors[middle.1 * row + middle.0] > max_error {
+        split_triangle(c, a, middle, errors, row, max_error, triangles);
+        split_triangle(b, c, middle, errors, row, max_error, triangles);
+    } else {
+        triangles.extend([a, b, c].iter().map(|&(x, z)| z * row + x));
+    }
+}
+
+// The values along the four edges of a chunk's height map entries.
+fn edges<T: Copy>(values: &[T], width: u32, depth: u32) -> [Vec<T>; 4] {
+    let (width, depth) = (width as usize, depth as usize);
+    let row = width + 1;
+    [
+        values[..row].to_vec(),
+        values[depth * row..].to_vec(),
+        values.iter().step_by(row).copied().collect(),
+        values.iter().skip(width).step_by(row).copied().collect(),
+    ]
+}
+
+// The largest distance between the heights along an edge and the outline
+// through the `kept` ones, which include both ends.
+fn outline_distance(edge: &[f32], kept: &[bool]) -> f32 {
+    let mut distance = 0.0;
+    let mut start = 0;
+    for end in 1..edge.len() {
+        if !kept[end] {
+            continue;
+        }
+        for i in start + 1..end {
+            let t = (i - start) as f32 / (end - start) as f32;
+            let outline = edge[start] + (edge[end] - edge[start]) * t;
+            distance = max(distance, (edge[i] - outline).abs());
+        }
+        start = end;
+    }
+    distance
+}
+
+// Builds the mesh from the height map entries of its vertices, the indices
+// of its triangles and the vertices on its edges, listed clockwise seen from
+// above with x to the right and z down.
+fn assemble_mesh(
+    chunk: &Chunk,
+    entries: &[usize],
+    mut indices: Vec<u32>,
+    perimeter: &[u32],
+    skirt_depth: f32,
+) -> ChunkMesh {
+    let layers = chunk.splat_layer_count as usize;
+    let vertex_count = entries.len() + perimeter.len();
+    let mut vertices = Vec::with_capacity(vertex_count * 3);
+    let mut normals = Vec::with_capacity(vertex_count * 3);
+    let mut colors = Vec::with_capacity(vertex_count * 3);
+    let mut splat_weights = Vec::with_capacity(vertex_count * layers);
+    let skirt_entries = perimeter.iter().map(|&vertex| entries[vertex as usize]);
+    for (i, entry) in entries.iter().copied().chain(skirt_entries).enumerate() {
+        let vertex = &chunk.vertices[entry * 3..entry * 3 + 3];
+        let lowered = if i < entries.len() { 0.0 } else { skirt_depth };
+        vertices.extend_from_slice(&[vertex[0], vertex[1] - lowered, vertex[2]]);
+        normals.extend_from_slice(&chunk.normals[entry * 3..entry * 3 + 3]);
+        colors.extend_from_slice(&chunk.colors[entry * 3..entry * 3 + 3]);
+        splat_weights.extend_from_slice(&chunk.splat_weights[entry * layers..(entry + 1) * layers]);
+    }
+
+    // Walking clockwise, the outside of the skirt is to the left, which
+    // keeps the winding of the triangles.
+    let skirt = entries.len() as u32;
+    let count = perimeter.len() as u32;
+    indices.reserve(count as usize * 6);
+    for i in 0..count {
+        let next = (i + 1) % count;
+        let (top, top_next) = (perimeter[i as usize], perimeter[next as usize]);
+        let (bottom, bottom_next) = (skirt + i, skirt + next);
+        indices.extend_from_slice(&[top, top_next, bottom, top_next, bottom_next, bottom]);
+    }
+
+    ChunkMesh {
+        skirt_depth,
+        vertices,
+        normals,
+        colors,
+        splat_weights,
+        splat_layer_count: chunk.splat_layer_count,
+        indices,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{max, min, TerrainConfig, TerrainGenerator};
+
+    #[test]
+    fn lod_meshes_subsample_the_chunk_and_skirts_cover_the_gaps() {
+        let config = TerrainConfig {
+            chunk_width: 32,
+            chunk_depth: 16,
+            ..TerrainConfig::default()
+        };
+        let generator = TerrainGenerator::new(3, config).unwrap();
+        let chunk = generator.generate_chunk(2, 1);
+        assert!(chunk.lod_mesh(5).is_err());
+        assert!(generator.generate_chunk_mesh(2, 1, 5).is_err());
+
+        let full = chunk.lod_mesh(0).unwrap();
+        assert_eq!(&full.vertices[..chunk.vertices.len()], &chunk.vertices[..]);
+        assert_eq!(&full.indices[..chunk.indices.len()], &chunk.indices[..]);
+
+        // The outline of the z = 0 edge at every level, at every height map
+        // entry along it.
+        let outlines: Vec<Vec<f32>> = (0..=4)
+            .map(|level| {
+                let mesh = chunk.lod_mesh(level).unwrap();
+                let (stride, cells_x) = (1 << level, 32 >> level);
+                let cells_z = 16 / stride;
+                let grid = (cells_x + 1) * (cells_z + 1);
+                assert_eq!(mesh.vertices.len(), (grid + 2 * (cells_x + cells_z)) * 3);
+                assert_eq!(
+                    mesh.indices.len(),
+                    (cells_x * cells_z + 2 * (cells_x + cells_z)) * 6
+                );
+                assert!(mesh
+                    .indices
+                    .iter()
+                    .all(|&index| (index as usize) < mesh.vertices.len() / 3));
+                assert_eq!(mesh.skirt_depth, full.skirt_depth);
+                assert_eq!(
+                    mesh.vertices[grid * 3 + 1],
+                    chunk.height_map[0] - mesh.skirt_depth
+                );
+
+                (0..=32)
+                    .map(|x| {
+                        let start = min(x / stride, cells_x - 1);
+                        let t = (x - start * stride) as f32 / stride as f32;
+                        let a = mesh.vertices[start * 3 + 1];
+                        let b = mesh.vertices[(start + 1) * 3 + 1];
+                        assert_eq!(a, chunk.height_map[start * stride]);
+                        a + (b - a) * t
+                    })
+                    .collect()
+            })
+            .collect();
+        assert!(full.skirt_depth > 0.0);
+        for fine in outlines.iter() {
+            for coarse in outlines.iter() {
+                for (fine, coarse) in fine.iter().zip(coarse) {
+                    assert!((fine - coarse).abs() <= full.skirt_depth + 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_meshes_stay_within_the_error_without_t_junctions() {
+        let config = TerrainConfig {
+            chunk_width: 32,
+            chunk_depth: 64,
+            ..TerrainConfig::default()
+        };
+        let generator = TerrainGenerator::new(5, config).unwrap();
+        let chunk = generator.generate_chunk(-1, 2);
+        assert!(chunk.adaptive_mesh(-1.0).is_err());
+        assert!(generator
+            .generate_chunk_adaptive_mesh(0, 0, f32::NAN)
+            .is_err());
+        let row = 33;
+
+        // The skirt depth and the outline of the x = 0 edge at every height
+        // map entry along it, for every error.
+        let mut edges = Vec::new();
+        let mut triangle_counts = Vec::new();
+        for &max_error in [0.0, 0.5, 2.0, 1000.0].iter() {
+            let mesh = chunk.adaptive_mesh(max_error).unwrap();
+            let points: Vec<(usize, usize, f32)> = mesh
+                .vertices
+                .chunks(3)
+                .map(|vertex| {
+                    (
+                        (vertex[0] + 32.0) as usize,
+                        (vertex[2] - 128.0) as usize,
+                        vertex[1],
+                    )
+                })
+                .collect();
+            // Every edge vertex has one below it in the skirt, whose
+            // triangles come last.
+            let on_edges = points
+                .iter()
+                .filter(|&&(x, z, _)| x == 0 || x == 32 || z == 0 || z == 64)
+                .count();
+            let grid_indices = &mesh.indices[..mesh.indices.len() - on_edges / 2 * 6];
+            let mut used = vec![false; chunk.height_map.len()];
+            for &vertex in grid_indices.iter() {
+                let (x, z, height) = points[vertex as usize];
+                assert_eq!(height, chunk.height_map[z * row + x]);
+                used[z * row + x] = true;
+            }
+
+            let mut largest_error: f32 = 0.0;
+            for triangle in grid_indices.chunks(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| points[triangle[i] as usize]);
+                let (ax, az) = (a.0 as f32, a.1 as f32);
+                let (abx, abz) = (b.0 as f32 - ax, b.1 as f32 - az);
+                let (acx, acz) = (c.0 as f32 - ax, c.1 as f32 - az);
+                let area = abx * acz - abz * acx;
+                assert!(area < 0.0);
+                for (p, q) in [(a, b), (b, c), (c, a)] {
+                    let steps = max(p.0.abs_diff(q.0), p.1.abs_diff(q.1));
+                    for step in 1..steps {
+                        let x = (p.0 * (steps - step) + q.0 * step) / steps;
+                        let z = (p.1 * (steps - step) + q.1 * step) / steps;
+                        assert!(!used[z * row + x]);
+                    }
+                }
+                for z in min(a.1, min(b.1, c.1))..=max(a.1, max(b.1, c.1)) {
+                    for x in min(a.0, min(b.0, c.0))..=max(a.0, max(b.0, c.0)) {
+                        let (px, pz) = (x as f32 - ax, z as f32 - az);
+                        let u = (px * acz - pz * acx) / area;
+                        let v = (abx * pz - abz * px) / area;
+                        if u >= 0.0 && v >= 0.0 && u + v <= 1.0 {
+                            let surface = a.2 + (b.2 - a.2) * u + (c.2 - a.2) * v;
+                            let error = (surface - chunk.height_map[z * row + x]).abs();
+                            largest_error = max(largest_error, error);
+                        }
+                    }
+                }
+            }
+            assert!(largest_error <= max_error + 1e-3);
+            triangle_counts.push(grid_indices.len() / 3);
+
+            let kept: Vec<usize> = (0..=64).filter(|&z| used[z * row]).collect();
+            let outline: Vec<f32> = (0..=64)
+                .map(|z| {
+                    let end = kept.iter().position(|&kept| kept >= z).unwrap();
+                    let (end, start) = (kept[end], kept[end.saturating_sub(1)]);
+                    let (start_height, end_height) =
+                        (chunk.height_map[start * row], chunk.height_map[end * row]);
+                    if end == start {
+                        return end_height;
+                    }
+                    let t = (z - start) as f32 / (end - start) as f32;
+                    start_height + (end_height - start_height) * t
+                })
+                .collect();
+            edges.push((mesh.skirt_depth, outline));
+        }
+        assert!(triangle_counts[0] <= 32 * 64 * 2);
+        assert!(triangle_counts[1] * 4 < triangle_counts[0]);
+        assert!(triangle_counts
+            .windows(2)
+            .all(|counts| counts[1] < counts[0]));
+        assert_eq!(triangle_counts[3], 4);
+
+        // A neighbour meshed with a smaller error is never further away than
+        // the skirt reaches.
+        for (i, (skirt_depth, coarse)) in edges.iter().enumerate() {
+            for (_, fine) in edges[..=i].iter() {
+                for (coarse, fine) in coarse.iter().zip(fine) {
+                    assert!((coarse - fine).abs() <= skirt_depth + 1e-4);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{max, min, TerrainConfig};
+
+// Grid based hydraulic erosion using the virtual pipe model from "Fast
+// Hydraulic Erosion Simulation and Visualization on GPU" (Mei, Decaudin and
+// Hu, 2007). Every cell holds a column of water connected to its four
+// neighbours by pipes. Rain fills the columns, the difference in water
+// surface height accelerates the flow through the pipes, and the resulting
+// velocity decides how much sediment the water dissolves, carries along and
+// deposits. Cells are one world unit wide and all heights are in world units
+// like the height map.
+
+// Order of the four pipes of a cell.
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+const TOP: usize = 2;
+const BOTTOM: usize = 3;
+
+// Below this depth a cell counts as dry when computing the water velocity.
+const MIN_WATER_DEPTH: f32 = 1e-4;
+// Lower bound of the tilt used for the sediment capacity, so water flowing
+// over flat ground still carries some sediment.
+const MIN_TILT_SINE: f32 = 0.05;
+
+fn sample_bilinear(values: &[f32], columns: usize, rows: usize, x: f32, z: f32) -> f32 {
+    let x = x.clamp(0.0, (columns - 1) as f32);
+    let z = z.clamp(0.0, (rows - 1) as f32);
+    let x0 = min(x.floor() as usize, columns - 2);
+    let z0 = min(z.floor() as usize, rows - 2);
+    let (tx, tz) = (x - x0 as f32, z - z0 as f32);
+    let top = values[z0 * columns + x0] * (1.0 - tx) + values[z0 * columns + x0 + 1] * tx;
+    let bottom =
+        values[(z0 + 1) * columns + x0] * (1.0 - tx) + values[(z0 + 1) * columns + x0 + 1] * tx;
+    top * (1.0 - tz) + bottom * tz
+}
+
+/// Runs `pipe_erosion_iterations` timesteps on `height_map` and returns the
+/// water depth and suspended sediment left in every cell afterwards.
+pub fn erode_pipe(
+    height_map: &mut [f32],
+    width: u32,
+    depth: u32,
+    config: &TerrainConfig,
+) -> (Vec<f32>, Vec<f32>) {
+    let columns = width as usize + 1;
+    let rows = depth as usize + 1;
+    let cell_count = columns * rows;
+    let time_step = config.pipe_erosion_time_step;
+
+    let mut water = vec![0.0; cell_count];
+    let mut new_water = vec![0.0; cell_count];
+    let mut sediment = vec![0.0; cell_count];
+    let mut moved_sediment = vec![0.0; cell_count];
+    let mut flux = vec![[0.0f32; 4]; cell_count];
+    let mut velocity = vec![[0.0f32; 2]; cell_count];
+
+    let neighbours = |x: usize, z: usize| -> [Option<usize>; 4] {
+        let index = z * columns + x;
+        [
+            if x > 0 { Some(index - 1) } else { None },
+            if x + 1 < columns {
+                Some(index + 1)
+            } else {
+                None
+            },
+            if z > 0 { Some(index - columns) } else { None },
+            if z + 1 < rows {
+                Some(index + columns)
+            } else {
+                None
+            },
+        ]
+    };
+
+    for _ in 0..config.pipe_erosion_iterations {
+        for water_depth in water.iter_mut() {
+            *water_depth += config.pipe_erosion_rain_rate * time_step;
+        }
+
+        // Outflow through each pipe, scaled down where it would drain more
+        // water than the cell holds.
+        for z in 0..rows {
+            for x in 0..columns {
+                let index = z * columns + x;
+                let surface = height_map[index] + water[index];
+                let mut total = 0.0;
+                for (pipe, neighbour) in neighbours(x, z).iter().enumerate() {
+                    flux[index][pipe] = match *neighbour {
+                        Some(neighbour) => max(
+                            0.0,
+                            flux[index][pipe]
+                                + time_step
+                                    * config.pipe_erosion_gravity
+                                    * (surface - height_map[neighbour] - water[neighbour]),
+                        ),
+                        None => 0.0,
+                    };
+                    total += flux[index][pipe];
+                }
+                if total * time_step > water[index] {
+                    let scale = water[index] / (total * time_step);
+                    for pipe_flux in flux[index].iter_mut() {
+                        *pipe_flux *= scale;
+                    }
+                }
+            }
+        }
+
+        // Water depth and velocity.
+        for z in 0..rows {
+            for x in 0..columns {
+                let index = z * columns + x;
+                let [left, right, top, bottom] = neighbours(x, z);
+                let inflow = |neighbour: Option<usize>, pipe: usize| match neighbour {
+                    Some(neighbour) => flux[neighbour][pipe],
+                    None => 0.0,
+                };
+                let from_left = inflow(left, RIGHT);
+                let from_right = inflow(right, LEFT);
+                let from_top = inflow(top, BOTTOM);
+                let from_bottom = inflow(bottom, TOP);
+                let outflow: f32 = flux[index].iter().sum();
+                new_water[index] = max(
+                    0.0,
+                    water[index]
+                        + time_step * (from_left + from_right + from_top + from_bottom - outflow),
+                );
+
+                let average_depth = (water[index] + new_water[index]) / 2.0;
+                velocity[index] = if average_depth > MIN_WATER_DEPTH {
+                    let flow_x =
+                        (from_left - flux[index][LEFT] + flux[index][RIGHT] - from_right) / 2.0;
+                    let flow_z =
+                        (from_top - flux[index][TOP] + flux[index][BOTTOM] - from_bottom) / 2.0;
+                    [flow_x / average_depth, flow_z / average_depth]
+                } else {
+                    [0.0, 0.0]
+                };
+            }
+        }
+
+        // Dissolving and depositing sediment.
+        for z in 0..rows {
+            for x in 0..columns {
+                let index = z * columns + x;
+                let height_at = |x: usize, z: usize| height_map[z * columns + x];
+                let gradient_x = (height_at(min(x + 1, columns - 1), z)
+                    - height_at(x.saturating_sub(1), z))
+                    / 2.0;
+                let gradient_z =
+                    (height_at(x, min(z + 1, rows - 1)) - height_at(x, z.saturating_sub(1))) / 2.0;
+                let gradient = (gradient_x * gradient_x + gradient_z * gradient_z).sqrt();
+                let tilt_sine = max(gradient / (1.0 + gradient * gradient).sqrt(), MIN_TILT_SINE);
+                let [velocity_x, velocity_z] = velocity[index];
+                let speed = (velocity_x * velocity_x + velocity_z * velocity_z).sqrt();
+                let capacity = config.pipe_erosion_sediment_capacity * tilt_sine * speed;
+
+                if capacity > sediment[index] {
+                    let amount = config.pipe_erosion_dissolve_speed
+                        * (capacity - sediment[index])
+                        * time_step;
+                    height_map[index] -= amount;
+                    sediment[index] += amount;
+                } else {
+                    let amount = config.pipe_erosion_deposit_speed
+                        * (sediment[index] - capacity)
+                        * time_step;
+                    height_map[index] += amount;
+                    sediment[index] -= amount;
+                }
+            }
+        }
+
+        // The water carries its sediment along, and part of it evaporates.
+        for z in 0..rows {
+            for x in 0..columns {
+                let index = z * columns + x;
+                let [velocity_x, velocity_z] = velocity[index];
+                moved_sediment[index] = sample_bilinear(
+                    &sediment,
+                    columns,
+                    rows,
+                    x as f32 - velocity_x * time_step,
+                    z as f32 - velocity_z * time_step,
+                );
+                water[index] =
+                    new_water[index] * (1.0 - config.pipe_erosion_evaporation * time_step);
+            }
+        }
+        std::mem::swap(&mut sediment, &mut moved_sediment);
+    }
+
+    (water, sediment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::erode_pipe;
+    use crate::TerrainConfig;
+
+    #[test]
+    fn water_carves_the_slope_and_deposits_at_its_foot() {
+        let config = TerrainConfig {
+            pipe_erosion_iterations: 200,
+            ..TerrainConfig::default()
+        };
+        let (width, depth) = (16, 16);
+        let columns = width as usize + 1;
+        let mut height_map: Vec<f32> = (0..columns * columns)
+            .map(|index| (index % columns) as f32 * 0.5)
+            .collect();
+        let original = height_map.clone();
+
+        let (water, sediment) = erode_pipe(&mut height_map, width, depth, &config);
+
+        assert!(height_map
+            .iter()
+            .chain(&water)
+            .chain(&sediment)
+            .all(|value| value.is_finite()));
+        assert!(water.iter().chain(&sediment).all(|&value| value >= 0.0));
+        let row = 8 * columns;
+        assert!(water[row] > water[row + columns - 1]);
+        assert!(height_map[row + 12] < original[row + 12]);
+        assert!(height_map[row + 3] > original[row + 3]);
+    }
+}
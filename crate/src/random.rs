@@ -0,0 +1,136 @@
+// Everything that turns a world seed into random numbers goes through this
+// module, so generated worlds only depend on code in this crate and not on
+// the hashing or RNG algorithms of `std` or `rand`, which are allowed to
+// change between releases.
+//
+// Hashes are built by feeding 64 bit words through the SplitMix64 finalizer.
+// The result for a given input must never change: if the algorithm has to be
+// changed, bump `SEED_HASH_VERSION` (which is mixed into every hash) so old
+// and new worlds are told apart, and update the golden values in the tests.
+
+pub const SEED_HASH_VERSION: u64 = 1;
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn hash_words(words: &[u64]) -> u64 {
+    let mut hash = mix(SEED_HASH_VERSION.wrapping_add(GOLDEN_GAMMA));
+    for &word in words {
+        hash = mix(hash.wrapping_add(GOLDEN_GAMMA) ^ word);
+    }
+    hash
+}
+
+/// Seed of the droplets eroding a single chunk (or erosion region). `kind`
+/// tells apart different uses of the same coordinates.
+pub fn region_seed(seed: u32, kind: u32, region_x: i32, region_z: i32) -> u64 {
+    hash_words(&[
+        seed as u64,
+        kind as u64,
+        region_x as u32 as u64,
+        region_z as u32 as u64,
+    ])
+}
+
+/// SplitMix64 generator used for the erosion droplets.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    // Uniform in [0, 1), built from the top 24 bits so every value is exactly
+    // representable.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn gen_range(&mut self, low: f32, high: f32) -> f32 {
+        let value = low + (high - low) * self.next_f32();
+        if value < high {
+            value
+        } else {
+            low
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_words, region_seed, Rng};
+    use crate::{TerrainConfig, TerrainGenerator};
+
+    fn checksum(values: &[f32]) -> u64 {
+        let words: Vec<u64> = values.iter().map(|value| value.to_bits() as u64).collect();
+        hash_words(&words)
+    }
+
+    fn config() -> TerrainConfig {
+        TerrainConfig {
+            chunk_width: 32,
+            chunk_depth: 32,
+            ..TerrainConfig::default()
+        }
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(hash_words(&[]), 10451216379200822465);
+        assert_eq!(region_seed(0, 0, 0, 0), 3786468572763441381);
+        assert_eq!(region_seed(12345, 0, -3, 7), 16769563990308260987);
+    }
+
+    #[test]
+    fn rng_is_stable() {
+        let mut rng = Rng::new(region_seed(42, 0, 1, 2));
+        assert_eq!(rng.next_u64(), 14248461214159677802);
+        assert_eq!(rng.next_f32(), 0.18852848);
+        assert_eq!(rng.gen_range(0.0, 32.0), 31.772085);
+    }
+
+    #[test]
+    fn height_maps_are_stable() {
+        let golden = [
+            (0, 0, 0, 2882682812780787696),
+            (1, 0, 0, 11840999442390639892),
+            (12345, -3, 7, 13384392603859194638),
+            (12345, 100, -100, 11533466750546749151),
+        ];
+        for &(seed, chunk_x, chunk_z, expected) in golden.iter() {
+            let generator = TerrainGenerator::new(seed, config()).unwrap();
+            let chunk = generator.generate_chunk(chunk_x, chunk_z);
+            assert_eq!(
+                checksum(&chunk.height_map),
+                expected,
+                "seed {} chunk ({}, {})",
+                seed,
+                chunk_x,
+                chunk_z
+            );
+        }
+    }
+
+    #[test]
+    fn apron_height_maps_are_stable() {
+        let config = TerrainConfig {
+            erosion_apron: 10,
+            ..config()
+        };
+        let generator = TerrainGenerator::new(9, config).unwrap();
+        let chunk = generator.generate_chunk(-1, 2);
+        assert_eq!(checksum(&chunk.height_map), 17913328091647724276);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{max, min, TerrainConfig};
+use serde::Serialize;
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// Flow routing and river extraction on a finished height map. Every cell
+// starts with one unit of water (its own area) and hands all of it to the
+// cells downstream, visiting cells from the highest to the lowest so each
+// cell has collected the water of its whole catchment before passing it on.
+// Cells without a lower neighbour keep their water; depressions are not
+// filled, so rivers end where they reach a pit or the edge of the map.
+
+/// How water leaving a cell is split between its eight neighbours. `D8`
+/// sends all of it to the steepest lower neighbour. `DInfinity` (Tarboton,
+/// 1997) picks the steepest direction on the eight triangular facets around
+/// the cell and splits the water between the two neighbours bounding it.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FlowRouting {
+    #[default]
+    D8,
+    DInfinity,
+}
+
+/// A point on a river, in world coordinates like the mesh vertices. `y` is
+/// the height of the water surface, which is the terrain height before any
+/// carving. `discharge` is the catchment area draining through the point in
+/// cells, and `width` the estimated width of the river there in world units.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RiverVertex {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub discharge: f32,
+    pub width: f32,
+}
+
+/// One stretch of a river network, from a source or confluence down to the
+/// next confluence or to where the river ends. A stretch ending at a
+/// confluence shares its last vertex with the stretch leaving it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct River {
+    pub vertices: Vec<RiverVertex>,
+}
+
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+// Up to two downstream cells of a cell, with the fraction of its water each
+// receives. The first receiver always gets the larger share.
+type Receivers = [Option<(usize, f32)>; 2];
+
+fn flow_directions(
+    height_map: &[f32],
+    width: u32,
+    depth: u32,
+    routing: FlowRouting,
+) -> Vec<Receivers> {
+    let row = width as isize + 1;
+    let neighbour = |x: isize, z: isize, n: usize| {
+        let (neighbour_x, neighbour_z) = (x + NEIGHBOURS[n].0, z + NEIGHBOURS[n].1);
+        if neighbour_x < 0
+            || neighbour_z < 0
+            || neighbour_x > width as isize
+            || neighbour_z > depth as isize
+        {
+            None
+        } else {
+            Some((neighbour_z * row + neighbour_x) as usize)
+        }
+    };
+
+    let mut directions = Vec::with_capacity(height_map.len());
+    for z in 0..=depth as isize {
+        for x in 0..=width as isize {
+            let height = height_map[(z * row + x) as usize];
+            let mut steepest: Option<(f32, Receivers)> = None;
+            match routing {
+                FlowRouting::D8 => {
+                    for n in 0..NEIGHBOURS.len() {
+                        if let Some(neighbour) = neighbour(x, z, n) {
+                            let distance = if n % 2 == 0 { 1.0 } else { SQRT_2 };
+                            let slope = (height - height_map[neighbour]) / distance;
+                            if slope > steepest.map_or(0.0, |(slope, _)| slope) {
+                                steepest = Some((slope, [Some((neighbour, 1.0)), None]));
+                            }
+                        }
+                    }
+                }
+                FlowRouting::DInfinity => {
+                    // Facet f lies between the cardinal neighbour 2 * (f / 2)
+                    // and the diagonal neighbour between it and the next one.
+                    for facet in 0..8 {
+                        let cardinal = (facet / 2) * 2;
+                        let diagonal = if facet % 2 == 0 {
+                            cardinal + 1
+                        } else {
+                            (cardinal + 7) % 8
+                        };
+                        let (cardinal, diagonal) =
+                            match (neighbour(x, z, cardinal), neighbour(x, z, diagonal)) {
+                                (Some(cardinal), Some(diagonal)) => (cardinal, diagonal),
+                                _ => continue,
+                            };
+                        let slope_cardinal = height - height_map[cardinal];
+                        let slope_across = height_map[cardinal] - height_map[diagonal];
+                        let mut angle = slope_across.atan2(slope_cardinal);
+                        let mut slope = slope_cardinal.hypot(slope_across);
+                        if angle < 0.0 {
+                            angle = 0.0;
+                            slope = slope_cardinal;
+                        } else if angle > FRAC_PI_4 {
+                            angle = FRAC_PI_4;
+                            slope = (height - height_map[diagonal]) / SQRT_2;
+                        }
+                        if slope > steepest.map_or(0.0, |(slope, _)| slope) {
+                            let to_diagonal = angle / FRAC_PI_4;
+                            let receivers = if to_diagonal == 0.0 {
+                                [Some((cardinal, 1.0)), None]
+                            } else if to_diagonal == 1.0 {
+                                [Some((diagonal, 1.0)), None]
+                            } else if to_diagonal > 0.5 {
+                                [
+                                    Some((diagonal, to_diagonal)),
+                                    Some((cardinal, 1.0 - to_diagonal)),
+                                ]
+                            } else {
+                                [
+                                    Some((cardinal, 1.0 - to_diagonal)),
+                                    Some((diagonal, to_diagonal)),
+                                ]
+                            };
+                            steepest = Some((slope, receivers));
+                        }
+                    }
+                }
+            }
+            directions.push(steepest.map_or([None, None], |(_, receivers)| receivers));
+        }
+    }
+    directions
+}
+
+fn accumulate(height_map: &[f32], directions: &[Receivers]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..height_map.len()).collect();
+    order.sort_by(|&a, &b| height_map[b].total_cmp(&height_map[a]));
+    let mut accumulation = vec![1.0; height_map.len()];
+    for cell in order {
+        for &(receiver, fraction) in directions[cell].iter().flatten() {
+            accumulation[receiver] += accumulation[cell] * fraction;
+        }
+    }
+    accumulation
+}
+
+/// The number of cells draining through every entry of `height_map`,
+/// including the entry itself.
+pub fn flow_accumulation(
+    height_map: &[f32],
+    width: u32,
+    depth: u32,
+    routing: FlowRouting,
+) -> Vec<f32> {
+    accumulate(
+        height_map,
+        &flow_directions(height_map, width, depth, routing),
+    )
+}
+
+/// Traces every cell whose catchment reaches `river_catchment_threshold`
+/// cells into river polylines, following the main flow direction of each
+/// cell. The map holds a chunk and `margin` entries around it, so rivers
+/// carry on across the chunk edges; only the stretches reaching into the
+/// chunk are kept, whole. `chunk_x` and `chunk_z` place the vertices in world
+/// coordinates. Returns the rivers together with the flow accumulation they
+/// were traced on, laid out like `height_map`.
+pub fn extract_rivers(
+    height_map: &[f32],
+    width: u32,
+    depth: u32,
+    chunk_x: i32,
+    chunk_z: i32,
+    margin: u32,
+    config: &TerrainConfig,
+) -> (Vec<River>, Vec<f32>) {
+    let (chunk_width, chunk_depth) = (width, depth);
+    let (width, depth) = (width + 2 * margin, depth + 2 * margin);
+    let row = width as usize + 1;
+    let directions = flow_directions(height_map, width, depth, config.river_flow_routing);
+    let accumulation = accumulate(height_map, &directions);
+    let is_river = |cell: usize| accumulation[cell] >= config.river_catchment_threshold;
+    let downstream = |cell: usize| match directions[cell][0] {
+        Some((receiver, _)) if is_river(receiver) => Some(receiver),
+        _ => None,
+    };
+
+    let mut upstream_count = vec![0u8; height_map.len()];
+    for cell in (0..height_map.len()).filter(|&cell| is_river(cell)) {
+        if let Some(receiver) = downstream(cell) {
+            upstream_count[receiver] += 1;
+        }
+    }
+
+    let (offset_x, offset_z) = (
+        chunk_x * chunk_width as i32 - margin as i32,
+        chunk_z * chunk_depth as i32 - margin as i32,
+    );
+    let in_chunk = |cell: usize| {
+        let (x, z) = ((cell % row) as u32, (cell / row) as u32);
+        (margin..=margin + chunk_width).contains(&x) && (margin..=margin + chunk_depth).contains(&z)
+    };
+    let vertex = |cell: usize| {
+        let discharge = accumulation[cell];
+        RiverVertex {
+            x: (offset_x + (cell % row) as i32) as f32,
+            y: height_map[cell],
+            z: (offset_z + (cell / row) as i32) as f32,
+            discharge,
+            width: config.river_width_factor * discharge.sqrt(),
+        }
+    };
+
+    // Every source and every confluence starts a stretch, which runs until
+    // the next confluence or the end of the river.
+    let mut rivers = Vec::new();
+    for start in 0..height_map.len() {
+        if !is_river(start) || upstream_count[start] == 1 {
+            continue;
+        }
+        let mut vertices = vec![vertex(start)];
+        let mut reaches_chunk = in_chunk(start);
+        let mut cell = start;
+        while let Some(next) = downstream(cell) {
+            vertices.push(vertex(next));
+            reaches_chunk |= in_chunk(next);
+            if upstream_count[next] > 1 {
+                break;
+            }
+            cell = next;
+        }
+        if vertices.len() > 1 && reaches_chunk {
+            rivers.push(River { vertices });
+        }
+    }
+    (rivers, accumulation)
+}
+
+/// Lowers the terrain under every river into a bed `river_depth_ratio` times
+/// as deep as the river is wide, with a parabolic cross section. Cells
+/// already below the bed are left alone. The map holds a chunk and `margin`
+/// entries around it, like for `extract_rivers`.
+#[allow(clippy::too_many_arguments)]
+pub fn carve_rivers(
+    height_map: &mut [f32],
+    width: u32,
+    depth: u32,
+    chunk_x: i32,
+    chunk_z: i32,
+    margin: u32,
+    rivers: &[River],
+    config: &TerrainConfig,
+) {
+    let offset_x = (chunk_x * width as i32 - margin as i32) as f32;
+    let offset_z = (chunk_z * depth as i32 - margin as i32) as f32;
+    let (width, depth) = (width + 2 * margin, depth + 2 * margin);
+    for vertex in rivers.iter().flat_map(|river| river.vertices.iter()) {
+        let radius = max(vertex.width / 2.0, 0.5);
+        let bed_depth = config.river_depth_ratio * vertex.width;
+        let (center_x, center_z) = (vertex.x - offset_x, vertex.z - offset_z);
+        let x0 = max(center_x - radius, 0.0).ceil() as usize;
+        let z0 = max(center_z - radius, 0.0).ceil() as usize;
+        let x1 = min(center_x + radius, width as f32).floor() as usize;
+        let z1 = min(center_z + radius, depth as f32).floor() as usize;
+        for z in z0..=z1 {
+            for x in x0..=x1 {
+                let distance = (x as f32 - center_x).hypot(z as f32 - center_z) / radius;
+                if distance > 1.0 {
+                    continue;
+                }
+                let bed = vertex.y - bed_depth * (1.0 - distance * distance);
+                let height = &mut height_map[z * (width as usize + 1) + x];
+                *height = min(*height, bed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{carve_rivers, extract_rivers, flow_accumulation, FlowRouting};
+    use crate::TerrainConfig;
+
+    // A valley along z = 8 draining towards x = 0.
+    fn valley() -> Vec<f32> {
+        (0..17 * 17)
+            .map(|index| {
+                let (x, z) = ((index % 17) as f32, (index / 17) as f32);
+                x * 0.5 + (z - 8.0).abs()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accumulates_the_whole_catchment() {
+        let height_map = valley();
+        for &routing in [FlowRouting::D8, FlowRouting::DInfinity].iter() {
+            let accumulation = flow_accumulation(&height_map, 16, 16, routing);
+            let outlet = 8 * 17;
+            assert!(accumulation.iter().all(|&cells| cells >= 1.0));
+            assert!(accumulation[outlet] > 200.0, "{:?}", routing);
+            assert_eq!(accumulation[16], 1.0);
+        }
+    }
+
+    #[test]
+    fn extracts_and_carves_the_valley_river() {
+        let config = TerrainConfig {
+            river_catchment_threshold: 20.0,
+            river_depth_ratio: 0.5,
+            ..TerrainConfig::default()
+        };
+        let mut height_map = valley();
+        let (rivers, _) = extract_rivers(&height_map, 16, 16, 1, 0, 0, &config);
+
+        // The valley itself, and the two edges of the chunk draining into it.
+        assert_eq!(rivers.len(), 3);
+        for river in rivers.iter() {
+            let outlet = river.vertices.last().unwrap();
+            assert_eq!((outlet.x, outlet.z), (16.0, 8.0));
+            assert!(river
+                .vertices
+                .windows(2)
+                .all(|pair| pair[1].y < pair[0].y && pair[1].discharge > pair[0].discharge));
+        }
+        assert!(rivers
+            .iter()
+            .any(|river| river.vertices.iter().all(|vertex| vertex.z == 8.0)));
+
+        let original = height_map.clone();
+        carve_rivers(&mut height_map, 16, 16, 1, 0, 0, &rivers, &config);
+        let outlet = 8 * 17;
+        assert!(height_map[outlet] < original[outlet]);
+        assert!(height_map.iter().zip(&original).all(|(a, b)| a <= b));
+    }
+}
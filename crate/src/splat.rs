@@ -0,0 +1,295 @@
+use crate::color::{range_weight, RULE_CURVATURE_FADE, RULE_HEIGHT_FADE, RULE_SLOPE_FADE};
+use crate::TerrainConfig;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+pub const MAX_SPLAT_LAYERS: usize = 8;
+
+const LAYER_EROSION_FADE: f32 = 0.5;
+
+/// One texture layer of a `SplatLayers` set. A vertex's weight for the layer
+/// is `strength` times how well the vertex fits the ranges, which use the
+/// same units as `ColorRule`. `erosion` is how far erosion moved the vertex
+/// from its noise height, in world units: negative where material was
+/// carved away and positive where sediment was deposited.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SplatLayer {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_height: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_slope: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_slope: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_curvature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_curvature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_erosion: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_erosion: Option<f32>,
+    #[serde(default = "full_strength")]
+    pub strength: f32,
+}
+
+fn full_strength() -> f32 {
+    1.0
+}
+
+impl SplatLayer {
+    pub fn new(name: &str) -> SplatLayer {
+        SplatLayer {
+            name: name.to_string(),
+            min_height: None,
+            max_height: None,
+            min_slope: None,
+            max_slope: None,
+            min_curvature: None,
+            max_curvature: None,
+            min_erosion: None,
+            max_erosion: None,
+            strength: 1.0,
+        }
+    }
+
+    fn weight(&self, height: f32, slope: f32, curvature: f32, erosion: f32) -> f32 {
+        self.strength
+            * range_weight(height, self.min_height, self.max_height, RULE_HEIGHT_FADE)
+            * range_weight(slope, self.min_slope, self.max_slope, RULE_SLOPE_FADE)
+            * range_weight(
+                curvature,
+                self.min_curvature,
+                self.max_curvature,
+                RULE_CURVATURE_FADE,
+            )
+            * range_weight(
+                erosion,
+                self.min_erosion,
+                self.max_erosion,
+                LAYER_EROSION_FADE,
+            )
+    }
+}
+
+/// Up to `MAX_SPLAT_LAYERS` texture layers. The weights of a vertex always
+/// add up to 1; where no layer matches, the first layer is used.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SplatLayers {
+    layers: Vec<SplatLayer>,
+}
+
+impl Default for SplatLayers {
+    /// Sand, grass, rock and snow.
+    fn default() -> SplatLayers {
+        let sand = SplatLayer {
+            max_height: Some(0.12),
+            ..SplatLayer::new("sand")
+        };
+        let grass = SplatLayer {
+            min_height: Some(0.12),
+            max_height: Some(0.6),
+            max_slope: Some(55.0),
+            ..SplatLayer::new("grass")
+        };
+        let rock = SplatLayer {
+            min_slope: Some(55.0),
+            ..SplatLayer::new("rock")
+        };
+        let snow = SplatLayer {
+            min_height: Some(0.6),
+            max_slope: Some(55.0),
+            ..SplatLayer::new("snow")
+        };
+        SplatLayers {
+            layers: vec![sand, grass, rock, snow],
+        }
+    }
+}
+
+impl SplatLayers {
+    pub fn new(layers: Vec<SplatLayer>) -> Result<SplatLayers, String> {
+        let layers = SplatLayers { layers };
+        layers.validate()?;
+        Ok(layers)
+    }
+
+    pub fn from_json(json: &str) -> Result<SplatLayers, String> {
+        let layers: SplatLayers = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        layers.validate()?;
+        Ok(layers)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn layers(&self) -> &[SplatLayer] {
+        &self.layers
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.layers.is_empty() || self.layers.len() > MAX_SPLAT_LAYERS {
+            return Err(format!(
+                "expected 1 to {} splat layers, got {}",
+                MAX_SPLAT_LAYERS,
+                self.layers.len()
+            ));
+        }
+        for layer in self.layers.iter() {
+            let ranges = [
+                ("height", layer.min_height, layer.max_height),
+                ("slope", layer.min_slope, layer.max_slope),
+                ("curvature", layer.min_curvature, layer.max_curvature),
+                ("erosion", layer.min_erosion, layer.max_erosion),
+            ];
+            for &(name, min, max) in ranges.iter() {
+                if min.into_iter().chain(max).any(|bound| !bound.is_finite()) {
+                    return Err(format!(
+                        "splat layer {} has a non-finite {} bound",
+                        layer.name, name
+                    ));
+                }
+                if let (Some(min), Some(max)) = (min, max) {
+                    if min > max {
+                        return Err(format!(
+                            "splat layer {} has min_{} {} above max_{} {}",
+                            layer.name, name, min, name, max
+                        ));
+                    }
+                }
+            }
+            if !(layer.strength > 0.0 && layer.strength <= 1.0) {
+                return Err(format!(
+                    "splat layer {} has strength {}, expected a value in (0, 1]",
+                    layer.name, layer.strength
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the normalized weight of every layer to `weights`. Heights are
+    /// fractions of `max_height` and slopes are in degrees.
+    pub fn weights_at(
+        &self,
+        height: f32,
+        slope: f32,
+        curvature: f32,
+        erosion: f32,
+        weights: &mut [f32],
+    ) {
+        let mut total = 0.0;
+        for (weight, layer) in weights.iter_mut().zip(self.layers.iter()) {
+            *weight = layer.weight(height, slope, curvature, erosion);
+            total += *weight;
+        }
+        if total > 0.0 {
+            for weight in weights.iter_mut() {
+                *weight /= total;
+            }
+        } else {
+            weights[0] = 1.0;
+        }
+    }
+}
+
+/// `layers.len()` weights per vertex, vertex by vertex.
+pub fn build_splat_weights(
+    height_map: &[f32],
+    normals: &[f32],
+    curvatures: &[f32],
+    erosion: &[f32],
+    layers: &SplatLayers,
+    config: &TerrainConfig,
+) -> Vec<f32> {
+    let layer_count = layers.len();
+    let mut weights = vec![0.0; height_map.len() * layer_count];
+    for (i, vertex_weights) in weights.chunks_mut(layer_count).enumerate() {
+        let slope = normals[i * 3 + 1].clamp(-1.0, 1.0).acos().to_degrees();
+        layers.weights_at(
+            height_map[i] / config.max_height,
+            slope,
+            curvatures[i],
+            erosion[i],
+            vertex_weights,
+        );
+    }
+    weights
+}
+
+/// Packs `layer_count` weights per vertex into RGBA8, four layers per texel.
+/// With more than four layers every vertex takes two texels, so the result
+/// holds `ceil(layer_count / 4) * 4` bytes per vertex with unused channels
+/// set to zero. Without layers there is nothing to pack.
+pub fn pack_splat_weights(weights: &[f32], layer_count: usize) -> Vec<u8> {
+    if layer_count == 0 {
+        return Vec::new();
+    }
+    let stride = layer_count.div_ceil(4) * 4;
+    let mut packed = vec![0; weights.len() / layer_count * stride];
+    for (vertex_weights, texels) in weights.chunks(layer_count).zip(packed.chunks_mut(stride)) {
+        for (weight, channel) in vertex_weights.iter().zip(texels.iter_mut()) {
+            *channel = (weight.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack_splat_weights, SplatLayer, SplatLayers};
+
+    #[test]
+    fn normalizes_weights() {
+        let layers = SplatLayers::default();
+        let mut weights = [0.0; 4];
+
+        layers.weights_at(0.3, 10.0, 0.0, 0.0, &mut weights);
+        assert_eq!(weights, [0.0, 1.0, 0.0, 0.0]);
+
+        layers.weights_at(0.3, 55.0, 0.0, 0.0, &mut weights);
+        assert!((weights[1] - 0.5).abs() < 1e-6 && (weights[2] - 0.5).abs() < 1e-6);
+
+        let sediment = SplatLayers::new(vec![
+            SplatLayer::new("base"),
+            SplatLayer {
+                min_erosion: Some(1.0),
+                ..SplatLayer::new("sediment")
+            },
+        ])
+        .unwrap();
+        let mut weights = [0.0; 2];
+        sediment.weights_at(0.3, 10.0, 0.0, 2.0, &mut weights);
+        assert_eq!(weights, [0.5, 0.5]);
+    }
+
+    #[test]
+    fn packs_into_rgba8() {
+        let weights = [0.0, 1.0, 0.25, 0.75, 0.0, 0.0];
+        assert_eq!(
+            pack_splat_weights(&weights, 3),
+            vec![0, 255, 64, 0, 191, 0, 0, 0]
+        );
+        assert!(pack_splat_weights(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn rejects_too_many_layers() {
+        let layers = vec![SplatLayer::new("layer"); 9];
+        assert!(SplatLayers::new(layers).is_err());
+        assert!(SplatLayers::from_json(&SplatLayers::default().to_json()).is_ok());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::TerrainConfig;
+
+// Thermal (talus) erosion: wherever the drop from a cell to one of its eight
+// neighbours is steeper than the talus angle, part of the excess material
+// slides down to that neighbour. All cells move material at the same time
+// (each iteration reads the heights from before the iteration), so the result
+// does not depend on the order cells are visited in.
+
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+pub fn erode_thermal(height_map: &mut [f32], width: u32, depth: u32, config: &TerrainConfig) {
+    let row = width as isize + 1;
+    let talus = config.thermal_erosion_talus_angle.to_radians().tan();
+    let thresholds: Vec<f32> = NEIGHBOURS
+        .iter()
+        .map(|&(dx, dz)| talus * ((dx * dx + dz * dz) as f32).sqrt())
+        .collect();
+    let mut deltas = vec![0.0; height_map.len()];
+    let mut excesses = [0.0; 8];
+
+    for _ in 0..config.thermal_erosion_iterations {
+        for delta in deltas.iter_mut() {
+            *delta = 0.0;
+        }
+        for z in 0..=depth as isize {
+            for x in 0..=width as isize {
+                let index = z * row + x;
+                let height = height_map[index as usize];
+                let mut total_excess = 0.0;
+                let mut max_excess: f32 = 0.0;
+                for (n, &(dx, dz)) in NEIGHBOURS.iter().enumerate() {
+                    let (neighbour_x, neighbour_z) = (x + dx, z + dz);
+                    excesses[n] = 0.0;
+                    if neighbour_x < 0
+                        || neighbour_z < 0
+                        || neighbour_x > width as isize
+                        || neighbour_z > depth as isize
+                    {
+                        continue;
+                    }
+                    let neighbour = (neighbour_z * row + neighbour_x) as usize;
+                    let excess = height - height_map[neighbour] - thresholds[n];
+                    if excess > 0.0 {
+                        excesses[n] = excess;
+                        total_excess += excess;
+                        max_excess = max_excess.max(excess);
+                    }
+                }
+                if total_excess <= 0.0 {
+                    continue;
+                }
+                // Moving half of the largest excess would level the cell with
+                // its steepest neighbour at the talus angle.
+                let moved = config.thermal_erosion_rate * max_excess / 2.0;
+                for (n, &(dx, dz)) in NEIGHBOURS.iter().enumerate() {
+                    if excesses[n] > 0.0 {
+                        let share = moved * excesses[n] / total_excess;
+                        deltas[((z + dz) * row + x + dx) as usize] += share;
+                        deltas[index as usize] -= share;
+                    }
+                }
+            }
+        }
+        for (height, delta) in height_map.iter_mut().zip(deltas.iter()) {
+            *height += delta;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::erode_thermal;
+    use crate::TerrainConfig;
+
+    #[test]
+    fn slumps_steep_slopes_to_the_talus_angle() {
+        let config = TerrainConfig {
+            thermal_erosion_iterations: 200,
+            thermal_erosion_talus_angle: 45.0,
+            thermal_erosion_rate: 0.5,
+            ..TerrainConfig::default()
+        };
+        let (width, depth) = (8, 8);
+        let mut height_map = vec![0.0; 81];
+        height_map[4 * 9 + 4] = 40.0;
+        let volume: f32 = height_map.iter().sum();
+
+        erode_thermal(&mut height_map, width, depth, &config);
+
+        let eroded_volume: f32 = height_map.iter().sum();
+        assert!((eroded_volume - volume).abs() < 1e-3);
+        for z in 0..9 {
+            for x in 0..8 {
+                let step = (height_map[z * 9 + x] - height_map[z * 9 + x + 1]).abs();
+                assert!(step < 1.05, "step of {} at ({}, {})", step, x, z);
+            }
+        }
+    }
+}
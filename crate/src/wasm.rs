@@ -0,0 +1,574 @@
+use crate::{
+    encode_glb, encode_obj, encode_stl, pack_splat_weights, BiomeTable, Chunk, ChunkMesh,
+    ColorRamp, HeightGraph, HeightMap, SplatLayers, TerrainConfig, TerrainGenerator,
+};
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+
+fn to_js_error(message: String) -> JsValue {
+    JsValue::from_str(&message)
+}
+
+#[wasm_bindgen]
+pub struct ChunkData {
+    chunk: Chunk,
+}
+
+#[wasm_bindgen]
+impl ChunkData {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.chunk.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn depth(&self) -> u32 {
+        self.chunk.depth
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height_map_array_length(&self) -> usize {
+        self.chunk.height_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertex_array_length(&self) -> usize {
+        self.chunk.vertices.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn indices_array_length(&self) -> usize {
+        self.chunk.indices.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn splat_weights_array_length(&self) -> usize {
+        self.chunk.splat_weights.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn splat_layer_count(&self) -> u32 {
+        self.chunk.splat_layer_count
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn water_map_array_length(&self) -> usize {
+        self.chunk.water_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sediment_map_array_length(&self) -> usize {
+        self.chunk.sediment_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn flow_map_array_length(&self) -> usize {
+        self.chunk.flow_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn deposition_map_array_length(&self) -> usize {
+        self.chunk.deposition_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn erosion_depth_map_array_length(&self) -> usize {
+        self.chunk.erosion_depth_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn flow_accumulation_map_array_length(&self) -> usize {
+        self.chunk.flow_accumulation_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn river_count(&self) -> usize {
+        self.chunk.rivers.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn lake_level_map_array_length(&self) -> usize {
+        self.chunk.lake_level_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn lake_count(&self) -> usize {
+        self.chunk.lakes.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn temperature_map_array_length(&self) -> usize {
+        self.chunk.temperature_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn moisture_map_array_length(&self) -> usize {
+        self.chunk.moisture_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn biome_map_array_length(&self) -> usize {
+        self.chunk.biome_map.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height_map(&self) -> u32 {
+        self.chunk.height_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertices(&self) -> u32 {
+        self.chunk.vertices.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn normals(&self) -> u32 {
+        self.chunk.normals.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn colors(&self) -> u32 {
+        self.chunk.colors.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> u32 {
+        self.chunk.indices.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn splat_weights(&self) -> u32 {
+        self.chunk.splat_weights.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn water_map(&self) -> u32 {
+        self.chunk.water_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sediment_map(&self) -> u32 {
+        self.chunk.sediment_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn flow_map(&self) -> u32 {
+        self.chunk.flow_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn deposition_map(&self) -> u32 {
+        self.chunk.deposition_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn erosion_depth_map(&self) -> u32 {
+        self.chunk.erosion_depth_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn flow_accumulation_map(&self) -> u32 {
+        self.chunk.flow_accumulation_map.as_ptr() as u32
+    }
+
+    pub fn rivers_json(&self) -> String {
+        serde_json::to_string(&self.chunk.rivers).unwrap()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn lake_level_map(&self) -> u32 {
+        self.chunk.lake_level_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn temperature_map(&self) -> u32 {
+        self.chunk.temperature_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn moisture_map(&self) -> u32 {
+        self.chunk.moisture_map.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn biome_map(&self) -> u32 {
+        self.chunk.biome_map.as_ptr() as u32
+    }
+
+    pub fn lakes_json(&self) -> String {
+        serde_json::to_string(&self.chunk.lakes).unwrap()
+    }
+
+    pub fn splat_weights_rgba8(&self) -> Vec<u8> {
+        pack_splat_weights(
+            &self.chunk.splat_weights,
+            self.chunk.splat_layer_count as usize,
+        )
+    }
+
+    pub fn height_map_png(&self, max_height: f32) -> Result<Vec<u8>, JsValue> {
+        HeightMap::from_chunk(&self.chunk)
+            .encode_png(max_height)
+            .map_err(to_js_error)
+    }
+
+    pub fn height_map_r16(&self, max_height: f32) -> Vec<u8> {
+        HeightMap::from_chunk(&self.chunk).encode_r16(max_height)
+    }
+
+    pub fn mesh_glb(&self) -> Result<Vec<u8>, JsValue> {
+        encode_glb(&[&self.chunk]).map_err(to_js_error)
+    }
+
+    pub fn mesh_obj(&self) -> String {
+        encode_obj(&[&self.chunk])
+    }
+
+    pub fn mesh_stl(&self, base_depth: Option<f32>) -> Result<Vec<u8>, JsValue> {
+        encode_stl(&HeightMap::from_chunk(&self.chunk), base_depth).map_err(to_js_error)
+    }
+
+    pub fn lod_mesh(&self, level: u32) -> Result<ChunkMeshData, JsValue> {
+        self.chunk
+            .lod_mesh(level)
+            .map(ChunkMeshData::from)
+            .map_err(to_js_error)
+    }
+
+    pub fn adaptive_mesh(&self, max_error: f32) -> Result<ChunkMeshData, JsValue> {
+        self.chunk
+            .adaptive_mesh(max_error)
+            .map(ChunkMeshData::from)
+            .map_err(to_js_error)
+    }
+}
+
+impl From<Chunk> for ChunkData {
+    fn from(chunk: Chunk) -> ChunkData {
+        ChunkData { chunk }
+    }
+}
+
+impl ChunkData {
+    pub fn into_chunk(self) -> Chunk {
+        self.chunk
+    }
+}
+
+#[wasm_bindgen]
+pub struct ChunkMeshData {
+    mesh: ChunkMesh,
+}
+
+#[wasm_bindgen]
+impl ChunkMeshData {
+    #[wasm_bindgen(getter)]
+    pub fn skirt_depth(&self) -> f32 {
+        self.mesh.skirt_depth
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertex_array_length(&self) -> usize {
+        self.mesh.vertices.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn indices_array_length(&self) -> usize {
+        self.mesh.indices.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn splat_weights_array_length(&self) -> usize {
+        self.mesh.splat_weights.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn splat_layer_count(&self) -> u32 {
+        self.mesh.splat_layer_count
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertices(&self) -> u32 {
+        self.mesh.vertices.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn normals(&self) -> u32 {
+        self.mesh.normals.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn colors(&self) -> u32 {
+        self.mesh.colors.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> u32 {
+        self.mesh.indices.as_ptr() as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn splat_weights(&self) -> u32 {
+        self.mesh.splat_weights.as_ptr() as u32
+    }
+}
+
+impl From<ChunkMesh> for ChunkMeshData {
+    fn from(mesh: ChunkMesh) -> ChunkMeshData {
+        ChunkMeshData { mesh }
+    }
+}
+
+#[allow(unused_macros)]
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        web_sys::console::log_1(&format!( $( $t )* ).into());
+    }
+}
+
+thread_local! {
+    static GENERATOR: RefCell<TerrainGenerator> =
+        RefCell::new(TerrainGenerator::new(0, TerrainConfig::default()).unwrap());
+}
+
+#[wasm_bindgen]
+pub fn set_seed(new_seed: u32) {
+    GENERATOR.with(|generator| generator.borrow_mut().set_seed(new_seed));
+}
+
+#[wasm_bindgen]
+pub fn get_config() -> TerrainConfig {
+    GENERATOR.with(|generator| generator.borrow().config())
+}
+
+#[wasm_bindgen]
+pub fn set_config(new_config: &TerrainConfig) -> Result<(), JsValue> {
+    GENERATOR.with(|generator| generator.borrow_mut().set_config_js(new_config))
+}
+
+#[wasm_bindgen]
+pub fn set_color_ramp(color_ramp: &ColorRamp) {
+    GENERATOR.with(|generator| generator.borrow_mut().set_color_ramp(color_ramp.clone()));
+}
+
+#[wasm_bindgen]
+pub fn set_splat_layers(splat_layers: Option<SplatLayers>) {
+    GENERATOR.with(|generator| generator.borrow_mut().set_splat_layers(splat_layers));
+}
+
+#[wasm_bindgen]
+pub fn set_biome_table(biome_table: Option<BiomeTable>) {
+    GENERATOR.with(|generator| generator.borrow_mut().set_biome_table(biome_table));
+}
+
+#[wasm_bindgen]
+pub fn set_height_graph(height_graph: Option<HeightGraph>) {
+    GENERATOR.with(|generator| generator.borrow_mut().set_height_graph(height_graph));
+}
+
+#[wasm_bindgen]
+impl TerrainConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> TerrainConfig {
+        TerrainConfig::default()
+    }
+
+    #[wasm_bindgen(js_name = validate)]
+    pub fn validate_js(&self) -> Result<(), JsValue> {
+        self.validate().map_err(to_js_error)
+    }
+}
+
+#[wasm_bindgen]
+impl TerrainGenerator {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js(seed: u32, config: &TerrainConfig) -> Result<TerrainGenerator, JsValue> {
+        TerrainGenerator::new(seed, *config).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(getter = seed)]
+    pub fn seed_js(&self) -> u32 {
+        self.seed()
+    }
+
+    #[wasm_bindgen(setter = seed)]
+    pub fn set_seed_js(&mut self, seed: u32) {
+        self.set_seed(seed);
+    }
+
+    #[wasm_bindgen(getter = config)]
+    pub fn config_js(&self) -> TerrainConfig {
+        self.config()
+    }
+
+    #[wasm_bindgen(js_name = set_config)]
+    pub fn set_config_js(&mut self, config: &TerrainConfig) -> Result<(), JsValue> {
+        self.set_config(*config).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(getter = color_ramp)]
+    pub fn color_ramp_js(&self) -> ColorRamp {
+        self.color_ramp().clone()
+    }
+
+    #[wasm_bindgen(setter = color_ramp)]
+    pub fn set_color_ramp_js(&mut self, color_ramp: &ColorRamp) {
+        self.set_color_ramp(color_ramp.clone());
+    }
+
+    #[wasm_bindgen(getter = splat_layers)]
+    pub fn splat_layers_js(&self) -> Option<SplatLayers> {
+        self.splat_layers().cloned()
+    }
+
+    #[wasm_bindgen(setter = splat_layers)]
+    pub fn set_splat_layers_js(&mut self, splat_layers: Option<SplatLayers>) {
+        self.set_splat_layers(splat_layers);
+    }
+
+    #[wasm_bindgen(getter = biome_table)]
+    pub fn biome_table_js(&self) -> Option<BiomeTable> {
+        self.biome_table().cloned()
+    }
+
+    #[wasm_bindgen(setter = biome_table)]
+    pub fn set_biome_table_js(&mut self, biome_table: Option<BiomeTable>) {
+        self.set_biome_table(biome_table);
+    }
+
+    #[wasm_bindgen(getter = height_graph)]
+    pub fn height_graph_js(&self) -> Option<HeightGraph> {
+        self.height_graph().cloned()
+    }
+
+    #[wasm_bindgen(setter = height_graph)]
+    pub fn set_height_graph_js(&mut self, height_graph: Option<HeightGraph>) {
+        self.set_height_graph(height_graph);
+    }
+
+    pub fn gen_chunk_data(&self, chunk_x: i32, chunk_z: i32) -> ChunkData {
+        ChunkData::from(self.generate_chunk(chunk_x, chunk_z))
+    }
+
+    pub fn gen_chunk_mesh(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        level: u32,
+    ) -> Result<ChunkMeshData, JsValue> {
+        self.generate_chunk_mesh(chunk_x, chunk_z, level)
+            .map(ChunkMeshData::from)
+            .map_err(to_js_error)
+    }
+
+    pub fn gen_chunk_adaptive_mesh(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        max_error: f32,
+    ) -> Result<ChunkMeshData, JsValue> {
+        self.generate_chunk_adaptive_mesh(chunk_x, chunk_z, max_error)
+            .map(ChunkMeshData::from)
+            .map_err(to_js_error)
+    }
+}
+
+#[wasm_bindgen]
+impl SplatLayers {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js() -> SplatLayers {
+        SplatLayers::default()
+    }
+
+    #[wasm_bindgen(js_name = from_json)]
+    pub fn from_json_js(json: &str) -> Result<SplatLayers, JsValue> {
+        SplatLayers::from_json(json).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(js_name = to_json)]
+    pub fn to_json_js(&self) -> String {
+        self.to_json()
+    }
+}
+
+#[wasm_bindgen]
+impl BiomeTable {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js() -> BiomeTable {
+        BiomeTable::default()
+    }
+
+    #[wasm_bindgen(js_name = from_json)]
+    pub fn from_json_js(json: &str) -> Result<BiomeTable, JsValue> {
+        BiomeTable::from_json(json).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(js_name = to_json)]
+    pub fn to_json_js(&self) -> String {
+        self.to_json()
+    }
+}
+
+#[wasm_bindgen]
+impl HeightGraph {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js() -> HeightGraph {
+        HeightGraph::default()
+    }
+
+    #[wasm_bindgen(js_name = from_json)]
+    pub fn from_json_js(json: &str) -> Result<HeightGraph, JsValue> {
+        HeightGraph::from_json(json).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(js_name = to_json)]
+    pub fn to_json_js(&self) -> String {
+        self.to_json()
+    }
+}
+
+#[wasm_bindgen]
+impl ColorRamp {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js() -> ColorRamp {
+        ColorRamp::default()
+    }
+
+    #[wasm_bindgen(js_name = preset)]
+    pub fn preset_js(name: &str) -> Result<ColorRamp, JsValue> {
+        ColorRamp::preset(name).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(js_name = from_json)]
+    pub fn from_json_js(json: &str) -> Result<ColorRamp, JsValue> {
+        ColorRamp::from_json(json).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(js_name = to_json)]
+    pub fn to_json_js(&self) -> String {
+        self.to_json()
+    }
+}
+
+#[wasm_bindgen]
+pub fn gen_chunk_data(chunk_x: i32, chunk_z: i32) -> ChunkData {
+    GENERATOR.with(|generator| generator.borrow().gen_chunk_data(chunk_x, chunk_z))
+}
+
+#[wasm_bindgen]
+pub fn gen_chunk_mesh(chunk_x: i32, chunk_z: i32, level: u32) -> Result<ChunkMeshData, JsValue> {
+    GENERATOR.with(|generator| generator.borrow().gen_chunk_mesh(chunk_x, chunk_z, level))
+}
+
+#[wasm_bindgen]
+pub fn gen_chunk_adaptive_mesh(
+    chunk_x: i32,
+    chunk_z: i32,
+    max_error: f32,
+) -> Result<ChunkMeshData, JsValue> {
+    GENERATOR.with(|generator| {
+        generator
+            .borrow()
+            .gen_chunk_adaptive_mesh(chunk_x, chunk_z, max_error)
+    })
+}
@@ -1,4 +1,3 @@
-import { CHUNK_WIDTH, CHUNK_DEPTH } from './crateConstants';
 import { Disposable } from './Disposable';
 import { Frustum } from './Frustum';
 import {
@@ -12,6 +11,7 @@ import { TerrainChunk } from './TerrainChunk';
 import { TerrainShaderLocations } from './terrainShader';
 import {
     GenerateChunkResponse,
+    GetChunkSizeResponse,
     RequestType,
     WPRequest,
     WPResponse,
@@ -20,8 +20,8 @@ import { WaterShaderLocations } from './waterShader';
 import { ExecutionCanceledError, WorkerPool } from './WorkerPool';
 
 interface TerrainParameters {
-    renderDistance: number;
-    getPlayerChunkPosition: () => ChunkPosition;
+    viewDistance: number;
+    getPlayerPosition: () => { x: number; z: number };
     gl: WebGL2RenderingContext;
     terrainShaderLocations: TerrainShaderLocations;
     waterShaderLocations: WaterShaderLocations;
@@ -31,19 +31,21 @@ interface TerrainParameters {
 
 export class Terrain extends Disposable {
     // eslint-disable-next-line max-len
-    private _renderDistance: number;
-    private _getPlayerChunkPosition: () => ChunkPosition;
+    private _viewDistance: number;
+    private _getPlayerPosition: () => { x: number; z: number };
+    private _chunkWidth = 0;
+    private _chunkDepth = 0;
     private _gl: WebGL2RenderingContext;
     private _terrainShaderLocations: TerrainShaderLocations;
     private _waterShaderLocations: WaterShaderLocations;
-    private _chunkLoader: LazyChunkLoader;
+    private _chunkLoader: LazyChunkLoader | null = null;
     private _chunks = new Map<SerializedChunkPosition, TerrainChunk | null>();
     private _chunkWorker: WorkerPool<WPRequest, WPResponse>;
 
     constructor(parameters: TerrainParameters) {
         super();
-        this._renderDistance = parameters.renderDistance;
-        this._getPlayerChunkPosition = parameters.getPlayerChunkPosition;
+        this._viewDistance = parameters.viewDistance;
+        this._getPlayerPosition = parameters.getPlayerPosition;
         this._gl = parameters.gl;
         this._terrainShaderLocations = parameters.terrainShaderLocations;
         this._waterShaderLocations = parameters.waterShaderLocations;
@@ -62,27 +64,54 @@ export class Terrain extends Disposable {
             );
         }
         this.add(this._chunkWorker);
-        const chunkLoaderActions: LazyChunkLoaderActions = {
-            loadChunk: (chunkPosition) => this._loadChunk(chunkPosition),
-            setChunkLoadingPriority: () => {},
-        };
-        this._chunkLoader = new LazyChunkLoader(
-            chunkLoaderActions,
-            this._renderDistance,
-        );
-        this.add(this._chunkLoader);
+        // Chunks are only loaded once the workers report how big they are.
+        this._chunkWorker
+            .execute({ type: RequestType.GetChunkSize }, this)
+            .then((response) => {
+                const {
+                    chunkWidth,
+                    chunkDepth,
+                } = response as GetChunkSizeResponse;
+                this._chunkWidth = chunkWidth;
+                this._chunkDepth = chunkDepth;
+                const chunkLoaderActions: LazyChunkLoaderActions = {
+                    loadChunk: (chunkPosition) =>
+                        this._loadChunk(chunkPosition),
+                    setChunkLoadingPriority: () => {},
+                };
+                this._chunkLoader = new LazyChunkLoader(
+                    chunkLoaderActions,
+                    Math.ceil(
+                        this._viewDistance / ((chunkWidth + chunkDepth) / 2),
+                    ),
+                );
+                this.add(this._chunkLoader);
+            })
+            .catch((error) => {
+                if (error instanceof ExecutionCanceledError) {
+                    return;
+                }
+                throw error;
+            });
     }
 
     public update(_dt: number): void {
-        const playerChunkPosition = this._getPlayerChunkPosition();
-        this._chunkLoader.loadChunksSurroundingPlayerChunkPosition(
-            playerChunkPosition,
-        );
+        if (!this._chunkLoader) {
+            return;
+        }
+        const { x, z } = this._getPlayerPosition();
+        this._chunkLoader.loadChunksSurroundingPlayerChunkPosition({
+            chunkX: Math.floor(x / this._chunkWidth),
+            chunkZ: Math.floor(z / this._chunkDepth),
+        });
     }
 
     public getHeightAtPlayerPosition(x: number, z: number): number {
-        const chunkX = Math.floor(x / CHUNK_WIDTH);
-        const chunkZ = Math.floor(z / CHUNK_DEPTH);
+        if (!this._chunkLoader) {
+            return 0;
+        }
+        const chunkX = Math.floor(x / this._chunkWidth);
+        const chunkZ = Math.floor(z / this._chunkDepth);
         const chunkPosition: ChunkPosition = { chunkX, chunkZ };
         const serializedChunkPosition = serializeChunkPosition(chunkPosition);
         const chunk = this._chunks.get(serializedChunkPosition);
@@ -90,8 +119,8 @@ export class Terrain extends Disposable {
             return 0;
         }
         return chunk.getHeightAtChunkOffset(
-            x - chunkX * CHUNK_WIDTH,
-            z - chunkZ * CHUNK_DEPTH,
+            x - chunkX * this._chunkWidth,
+            z - chunkZ * this._chunkDepth,
         );
     }
 
@@ -128,6 +157,8 @@ export class Terrain extends Disposable {
         };
         const chunk = new TerrainChunk({
             chunkPosition,
+            chunkWidth: this._chunkWidth,
+            chunkDepth: this._chunkDepth,
             gl: this._gl,
             waterShaderLocations: this._waterShaderLocations,
         });
@@ -1,11 +1,6 @@
 import { vec3 } from 'gl-matrix';
 import { Box3 } from './Box3';
-import {
-    CHUNK_DEPTH,
-    CHUNK_WIDTH,
-    EROSION_OCEAN_HEIGHT,
-    MAX_HEIGHT,
-} from './crateConstants';
+import { EROSION_OCEAN_HEIGHT, MAX_HEIGHT } from './crateConstants';
 import { Disposable } from './Disposable';
 import { ChunkPosition } from './LazyChunkLoader';
 import { TerrainShaderLocations } from './terrainShader';
@@ -13,6 +8,8 @@ import { WaterShaderLocations } from './waterShader';
 
 export interface TerrainChunkParameters {
     chunkPosition: ChunkPosition;
+    chunkWidth: number;
+    chunkDepth: number;
     gl: WebGL2RenderingContext;
     waterShaderLocations: WaterShaderLocations;
 }
@@ -31,6 +28,8 @@ export class TerrainChunk extends Disposable {
     private _initialized = false;
     private _heightMap!: Float32Array;
     private _chunkPosition: ChunkPosition;
+    private _chunkWidth: number;
+    private _chunkDepth: number;
     private _indicesCount!: number;
     private _boundingBox: Box3;
     private _vao!: WebGLVertexArrayObject;
@@ -69,16 +68,18 @@ export class TerrainChunk extends Disposable {
         });
         const { gl } = parameters;
         this._chunkPosition = parameters.chunkPosition;
+        this._chunkWidth = parameters.chunkWidth;
+        this._chunkDepth = parameters.chunkDepth;
         this._createWaterVao(gl, parameters.waterShaderLocations);
         const boxMin = vec3.fromValues(
-            this._chunkPosition.chunkX * CHUNK_WIDTH,
+            this._chunkPosition.chunkX * this._chunkWidth,
             0,
-            this._chunkPosition.chunkZ * CHUNK_DEPTH,
+            this._chunkPosition.chunkZ * this._chunkDepth,
         );
         const boxMax = vec3.add(
             vec3.create(),
             boxMin,
-            vec3.fromValues(CHUNK_WIDTH, MAX_HEIGHT, CHUNK_DEPTH),
+            vec3.fromValues(this._chunkWidth, MAX_HEIGHT, this._chunkDepth),
         );
         this._boundingBox = new Box3(boxMin, boxMax);
     }
@@ -165,8 +166,10 @@ export class TerrainChunk extends Disposable {
         locations: WaterShaderLocations,
     ): void {
         const { chunkX, chunkZ } = this._chunkPosition;
-        const offsetX = chunkX * CHUNK_WIDTH;
-        const offsetZ = chunkZ * CHUNK_DEPTH;
+        const chunkWidth = this._chunkWidth;
+        const chunkDepth = this._chunkDepth;
+        const offsetX = chunkX * chunkWidth;
+        const offsetZ = chunkZ * chunkDepth;
         const WATER_HEIGHT = MAX_HEIGHT * EROSION_OCEAN_HEIGHT;
         const vertices = new Float32Array([
             offsetX,
@@ -174,17 +177,17 @@ export class TerrainChunk extends Disposable {
             offsetZ,
             offsetX,
             WATER_HEIGHT,
-            offsetZ + CHUNK_DEPTH,
-            offsetX + CHUNK_WIDTH,
+            offsetZ + chunkDepth,
+            offsetX + chunkWidth,
             WATER_HEIGHT,
             offsetZ,
             offsetX,
             WATER_HEIGHT,
-            offsetZ + CHUNK_DEPTH,
-            offsetX + CHUNK_WIDTH,
+            offsetZ + chunkDepth,
+            offsetX + chunkWidth,
             WATER_HEIGHT,
-            offsetZ + CHUNK_DEPTH,
-            offsetX + CHUNK_WIDTH,
+            offsetZ + chunkDepth,
+            offsetX + chunkWidth,
             WATER_HEIGHT,
             offsetZ,
         ]);
@@ -221,20 +224,21 @@ export class TerrainChunk extends Disposable {
         const floorX = Math.floor(x);
         const floorZ = Math.floor(z);
 
-        if (floorX >= CHUNK_WIDTH || floorZ >= CHUNK_DEPTH) {
+        const chunkWidth = this._chunkWidth;
+        if (floorX >= chunkWidth || floorZ >= this._chunkDepth) {
             return DEFAULT;
         }
 
         const gridOffsetX = x - floorX;
         const gridOffsetZ = z - floorZ;
 
-        const heightTopLeft = heightMap[floorZ * (CHUNK_WIDTH + 1) + floorX];
+        const heightTopLeft = heightMap[floorZ * (chunkWidth + 1) + floorX];
         const heightTopRight =
-            heightMap[floorZ * (CHUNK_WIDTH + 1) + (floorX + 1)];
+            heightMap[floorZ * (chunkWidth + 1) + (floorX + 1)];
         const heightBottomLeft =
-            heightMap[(floorZ + 1) * (CHUNK_WIDTH + 1) + floorX];
+            heightMap[(floorZ + 1) * (chunkWidth + 1) + floorX];
         const heightBottomRight =
-            heightMap[(floorZ + 1) * (CHUNK_WIDTH + 1) + (floorX + 1)];
+            heightMap[(floorZ + 1) * (chunkWidth + 1) + (floorX + 1)];
 
         const heightLeft =
             heightTopLeft + (heightBottomLeft - heightTopLeft) * gridOffsetZ;
@@ -1,4 +1,2 @@
-export const CHUNK_WIDTH = 1024;
-export const CHUNK_DEPTH = 1024;
 export const MAX_HEIGHT = 512;
 export const EROSION_OCEAN_HEIGHT = 0.37;
@@ -1,18 +1,12 @@
 import { vec3, vec4, mat4 } from 'gl-matrix';
 import throttle from 'lodash.throttle';
-import {
-    CHUNK_DEPTH,
-    CHUNK_WIDTH,
-    EROSION_OCEAN_HEIGHT,
-    MAX_HEIGHT,
-} from './crateConstants';
+import { EROSION_OCEAN_HEIGHT, MAX_HEIGHT } from './crateConstants';
 import { FirstPersonCamera } from './FirstPersonCamera';
 import {
     attachFramebufferColorTexture,
     checkFramebufferStatus,
     loadTexturePower2,
 } from './glUtil';
-import { ChunkPosition } from './LazyChunkLoader';
 import { makeSkyShader, SkyShaderRenderParameters } from './skyShader';
 import { Terrain } from './Terrain';
 import {
@@ -91,13 +85,8 @@ const waterShader = makeWaterShader(gl, {
 });
 
 const terrain = new Terrain({
-    getPlayerChunkPosition: (): ChunkPosition => {
-        return {
-            chunkX: Math.floor(camera.x / CHUNK_WIDTH),
-            chunkZ: Math.floor(camera.z / CHUNK_DEPTH),
-        };
-    },
-    renderDistance: Math.ceil(camera.far / ((CHUNK_WIDTH + CHUNK_DEPTH) / 2)),
+    getPlayerPosition: () => ({ x: camera.x, z: camera.z }),
+    viewDistance: camera.far,
     gl,
     terrainShaderLocations: terrainShader.locations,
     waterShaderLocations: waterShader.locations,
@@ -1,9 +1,10 @@
-import { CHUNK_DEPTH, CHUNK_WIDTH } from './crateConstants';
 import {
     Request,
     RequestType,
     SetSeedRequest,
     SetSeedResponse,
+    GetChunkSizeRequest,
+    GetChunkSizeResponse,
     GenerateChunkRequest,
     GenerateChunkResponse,
 } from './terrainWorkerTypes';
@@ -17,9 +18,11 @@ async function generateChunk(request: GenerateChunkRequest): Promise<void> {
     const { requestId, chunkX, chunkZ } = request;
     const [wasm, crate] = await importsP;
     const chunkData = crate.gen_chunk_data(chunkX, chunkZ);
-    const heightMapArrayLength = (CHUNK_WIDTH + 1) * (CHUNK_DEPTH + 1);
-    const vertexArrayLength = heightMapArrayLength * 3;
-    const indicesArrayLength = CHUNK_WIDTH * CHUNK_DEPTH * 6;
+    const {
+        height_map_array_length: heightMapArrayLength,
+        vertex_array_length: vertexArrayLength,
+        indices_array_length: indicesArrayLength,
+    } = chunkData;
     const heightMap = new Float32Array(
         wasm.memory.buffer,
         chunkData.height_map,
@@ -79,12 +82,31 @@ async function setSeed(request: SetSeedRequest): Promise<void> {
     (self as any).postMessage(response);
 }
 
+async function getChunkSize(request: GetChunkSizeRequest): Promise<void> {
+    const { requestId } = request;
+    const [, crate] = await importsP;
+    const config = crate.get_config();
+    const response: GetChunkSizeResponse = {
+        type: RequestType.GetChunkSize,
+        requestId,
+        chunkWidth: config.chunk_width,
+        chunkDepth: config.chunk_depth,
+    };
+    config.free();
+    // eslint-disable-next-line max-len
+    // eslint-disable-next-line @typescript-eslint/no-unsafe-member-access, @typescript-eslint/no-unsafe-call, @typescript-eslint/no-explicit-any
+    (self as any).postMessage(response);
+}
+
 self.onmessage = (event: MessageEvent<Request>) => {
     const request = event.data;
     switch (request.type) {
         case RequestType.SetSeed:
             void setSeed(request);
             break;
+        case RequestType.GetChunkSize:
+            void getChunkSize(request);
+            break;
         case RequestType.GenerateChunk:
             void generateChunk(request);
     }
@@ -1,5 +1,6 @@
 export enum RequestType {
     SetSeed,
+    GetChunkSize,
     GenerateChunk,
 }
 
@@ -18,6 +19,15 @@ export interface SetSeedResponse extends ResponseBase {
     type: RequestType.SetSeed;
 }
 
+export interface GetChunkSizeRequest extends RequestBase {
+    type: RequestType.GetChunkSize;
+}
+export interface GetChunkSizeResponse extends ResponseBase {
+    type: RequestType.GetChunkSize;
+    chunkWidth: number;
+    chunkDepth: number;
+}
+
 export interface GenerateChunkRequest extends RequestBase {
     type: RequestType.GenerateChunk;
     chunkX: number;
@@ -32,12 +42,20 @@ export interface GenerateChunkResponse extends ResponseBase {
     indices: Uint32Array;
 }
 
-export type Request = SetSeedRequest | GenerateChunkRequest;
-export type Response = SetSeedResponse | GenerateChunkResponse;
+export type Request =
+    | SetSeedRequest
+    | GetChunkSizeRequest
+    | GenerateChunkRequest;
+export type Response =
+    | SetSeedResponse
+    | GetChunkSizeResponse
+    | GenerateChunkResponse;
 
 export type WPRequest =
     | Omit<SetSeedRequest, 'requestId'>
+    | Omit<GetChunkSizeRequest, 'requestId'>
     | Omit<GenerateChunkRequest, 'requestId'>;
 export type WPResponse =
     | Omit<SetSeedResponse, 'requestId'>
+    | Omit<GetChunkSizeResponse, 'requestId'>
     | Omit<GenerateChunkResponse, 'requestId'>;